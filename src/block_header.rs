@@ -0,0 +1,72 @@
+use crate::target::U256;
+use byteorder::{LittleEndian, WriteBytesExt};
+use sha2::{Sha256, Digest};
+
+// The fixed size part of a block that is hashed for proof of work
+//
+// Transactions are committed to through the Merkle root so the cost of hashing
+// a header doesn't depend on how many transactions the block holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub previous_hash: String,
+    pub merkle_root: String,
+    pub timestamp: i64,
+    // Proof of work target in compact form
+    pub bits: u32,
+    pub nonce: u64
+}
+
+impl<'a> BlockHeader {
+    pub const VERSION: u32 = 1;
+
+    pub fn new(previous_hash: &'a str, merkle_root: &'a str, timestamp: i64, bits: u32, nonce: u64) -> BlockHeader {
+        BlockHeader {
+            version: BlockHeader::VERSION,
+            previous_hash: previous_hash.to_string(),
+            merkle_root: merkle_root.to_string(),
+            timestamp,
+            bits,
+            nonce
+        }
+    }
+
+    // Converts all header fields to a byte vector
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.write_u32::<LittleEndian>(self.version).expect("Unable to serialize version");
+        bytes.extend_from_slice(self.previous_hash.as_bytes());
+        bytes.extend_from_slice(self.merkle_root.as_bytes());
+        bytes.write_i64::<LittleEndian>(self.timestamp).expect("Unable to serialize timestamp");
+        bytes.write_u32::<LittleEndian>(self.bits).expect("Unable to serialize bits");
+        bytes.write_u64::<LittleEndian>(self.nonce).expect("Unable to serialize nonce");
+        bytes
+    }
+
+    // Hashes all header fields, this is the hash of the block
+    pub fn as_hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.input(self.as_bytes());
+        let result = hasher.result();
+        hex::encode(result)
+    }
+
+    // Decodes the proof of work target from the compact bits
+    pub fn target(&self) -> U256 {
+        U256::from_compact(self.bits)
+    }
+
+    // Checks a header hash, read as a big endian number, is at or below the target
+    pub fn hash_meets_target(&self, hash: &str) -> bool {
+        let mut bytes = [0u8; 32];
+        match hex::decode_to_slice(hash, &mut bytes) {
+            Ok(_) => U256::from_be_bytes(bytes) <= self.target(),
+            Err(_) => false
+        }
+    }
+
+    // The expected number of hashes needed to mine this header
+    pub fn work(&self) -> U256 {
+        self.target().work()
+    }
+}
@@ -0,0 +1,351 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::signed_transaction::SignedTransaction;
+
+// Dependencies between pending transactions, by position in the pool
+//
+// A transaction depends on the pending transactions whose outputs it spends and
+// on the one from the same sender with the next lower nonce, so a sender's nonce
+// chain links each transaction to the one before it only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolGraph {
+    pub parents: Vec<Vec<usize>>,
+    pub children: Vec<Vec<usize>>
+}
+
+impl PoolGraph {
+    pub fn new(pending: &[SignedTransaction]) -> PoolGraph {
+        let by_hash: HashMap<&str, usize> = pending.iter().enumerate()
+            .map(|(index, transaction)| (transaction.hash.as_str(), index))
+            .collect();
+        let mut parents: Vec<Vec<usize>> = pending.iter().enumerate()
+            .map(|(index, transaction)| {
+                transaction.transaction.inputs.iter()
+                    .filter_map(|input| by_hash.get(input.hash.as_str()).copied())
+                    .filter(|parent| *parent != index)
+                    .collect()
+            })
+            .collect();
+
+        let mut by_sender: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (index, transaction) in pending.iter().enumerate() {
+            by_sender.entry(transaction.transaction.from.as_str()).or_default().push(index);
+        }
+        for indexes in by_sender.values_mut() {
+            indexes.sort_by_key(|index| pending[*index].transaction.nonce);
+            for pair in indexes.windows(2) {
+                parents[pair[1]].push(pair[0]);
+            }
+        }
+
+        let mut children = vec![Vec::new(); pending.len()];
+        for (index, parents) in parents.iter_mut().enumerate() {
+            parents.sort_unstable();
+            parents.dedup();
+            for parent in parents.iter() {
+                children[*parent].push(index);
+            }
+        }
+
+        PoolGraph {
+            parents,
+            children
+        }
+    }
+
+    // Orders the pool so each transaction comes after its parents, otherwise
+    // keeping pool order
+    //
+    // Transactions left out depend on themselves and can never be mined.
+    pub fn order(&self) -> Vec<usize> {
+        let mut waiting: Vec<usize> = self.parents.iter().map(Vec::len).collect();
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..waiting.len())
+            .filter(|index| waiting[*index] == 0)
+            .map(Reverse)
+            .collect();
+
+        let mut order = Vec::new();
+        while let Some(Reverse(index)) = ready.pop() {
+            order.push(index);
+            for child in self.children[index].iter() {
+                waiting[*child] -= 1;
+                if waiting[*child] == 0 {
+                    ready.push(Reverse(*child));
+                }
+            }
+        }
+        order
+    }
+
+    // Lists a transaction and its ancestors that are still pending, each after
+    // its own pending ancestors
+    fn package(&self, index: usize, states: &[State]) -> Vec<usize> {
+        let mut package = Vec::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![(index, false)];
+        while let Some((index, is_expanded)) = stack.pop() {
+            if is_expanded {
+                package.push(index);
+            } else if visited.insert(index) {
+                stack.push((index, true));
+                stack.extend(self.parents[index].iter()
+                    .filter(|parent| states[**parent] == State::Pending && !visited.contains(*parent))
+                    .map(|parent| (*parent, false)));
+            }
+        }
+        package
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Pending,
+    Picked,
+    Dropped
+}
+
+// Combined fee, size and number of transactions in a package
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Package {
+    fee: i128,
+    size: i128,
+    count: usize
+}
+
+impl Package {
+    fn new(transaction: &SignedTransaction) -> Package {
+        Package {
+            fee: i128::from(transaction.transaction.fee),
+            size: transaction.size() as i128,
+            count: 1
+        }
+    }
+
+    fn add(&self, other: &Package) -> Package {
+        Package {
+            fee: self.fee + other.fee,
+            size: self.size + other.size,
+            count: self.count + other.count
+        }
+    }
+
+    fn remove(&self, other: &Package) -> Package {
+        Package {
+            fee: self.fee - other.fee,
+            size: self.size - other.size,
+            count: self.count - other.count
+        }
+    }
+}
+
+// A transaction's package as it was when queued, ordered by fee per byte and
+// then by position in the pool
+#[derive(Debug)]
+struct Candidate {
+    package: Package,
+    index: usize
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        (self.package.fee * other.package.size).cmp(&(other.package.fee * self.package.size))
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Candidate) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+// Picks pending transactions for a block by ancestor package
+//
+// A transaction's package is itself plus its ancestors not picked yet, see
+// PoolGraph. The package with the highest combined fee per byte is picked next,
+// each transaction after its ancestors, until no package fits in
+// max_transactions. A low fee parent is therefore mined along with a high fee
+// child paying for it.
+//
+// The combined fee and size of every package are worked out once and then
+// reduced as ancestors are picked or dropped, rather than rebuilt each time.
+// Each picked transaction is passed to apply, which adds it to the block or
+// returns false if it's invalid.
+//
+// Returns the picked transactions and the transactions left pending. Invalid
+// transactions are dropped.
+pub fn select_packages<F>(pending: Vec<SignedTransaction>, max_transactions: usize, mut apply: F) -> (Vec<SignedTransaction>, Vec<SignedTransaction>)
+    where F: FnMut(&SignedTransaction) -> bool
+{
+    let graph = PoolGraph::new(&pending);
+    let order = graph.order();
+    let own: Vec<Package> = pending.iter().map(Package::new).collect();
+
+    let mut states = vec![State::Dropped; pending.len()];
+    for index in order.iter() {
+        states[*index] = State::Pending;
+    }
+
+    // A single parent's package plus the parent is exact, otherwise the
+    // ancestors are walked so one reached through several parents counts once
+    let mut packages = vec![Package::default(); pending.len()];
+    for index in order.iter().copied() {
+        packages[index] = match graph.parents[index].as_slice() {
+            [parent] => packages[*parent].add(&own[index]),
+            _ => graph.package(index, &states).into_iter()
+                .fold(Package::default(), |total, ancestor| total.add(&own[ancestor]))
+        };
+    }
+
+    let mut queue: BinaryHeap<Candidate> = order.iter()
+        .map(|index| Candidate { package: packages[*index], index: *index })
+        .collect();
+    let mut picked = Vec::new();
+    while let Some(candidate) = queue.pop() {
+        let slots = max_transactions.saturating_sub(picked.len());
+        if slots == 0 {
+            break;
+        }
+        // Skip candidates picked, dropped or changed since they were queued
+        let index = candidate.index;
+        if states[index] != State::Pending || candidate.package != packages[index] || candidate.package.count > slots {
+            continue;
+        }
+
+        let mut changed = HashSet::new();
+        for index in graph.package(index, &states) {
+            states[index] = if apply(&pending[index]) {
+                picked.push(index);
+                State::Picked
+            } else {
+                State::Dropped
+            };
+
+            // Take the transaction out of its pending descendants' packages
+            let mut stack = graph.children[index].clone();
+            let mut visited = HashSet::new();
+            while let Some(descendant) = stack.pop() {
+                if states[descendant] != State::Pending || !visited.insert(descendant) {
+                    continue;
+                }
+                packages[descendant] = packages[descendant].remove(&own[index]);
+                changed.insert(descendant);
+                stack.extend(graph.children[descendant].iter().copied());
+            }
+        }
+
+        for index in changed {
+            if states[index] == State::Pending {
+                queue.push(Candidate { package: packages[index], index });
+            }
+        }
+    }
+
+    let mut remaining = Vec::new();
+    let mut transactions = Vec::new();
+    let mut pending: Vec<Option<SignedTransaction>> = pending.into_iter().map(Some).collect();
+    for index in picked {
+        transactions.extend(pending[index].take());
+    }
+    for (index, transaction) in pending.into_iter().enumerate() {
+        if states[index] == State::Pending {
+            remaining.extend(transaction);
+        }
+    }
+    (transactions, remaining)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypair::Keypair;
+    use crate::transaction::{OutPoint, Output, Transaction};
+
+    fn transaction(keypair: &Keypair, nonce: u64, inputs: Vec<OutPoint>, fee: i64) -> SignedTransaction {
+        let transaction = Transaction::create(&keypair.export_public_key(), nonce, inputs, vec![Output::new("recipient", 10)], fee);
+        SignedTransaction::create(transaction, keypair)
+    }
+
+    fn hashes(transactions: &[SignedTransaction]) -> Vec<&str> {
+        transactions.iter().map(|transaction| transaction.hash.as_str()).collect()
+    }
+
+    #[test]
+    fn child_pays_for_parent() {
+        let (alice, bob, carol) = (Keypair::new(), Keypair::new(), Keypair::new());
+        let parent = transaction(&alice, 0, vec![OutPoint::new("coins", 0)], 0);
+        let other = transaction(&bob, 0, vec![OutPoint::new("coins", 1)], 50);
+        let child = transaction(&carol, 0, vec![parent.outpoint(0)], 500);
+
+        let pending = vec![parent.clone(), other.clone(), child.clone()];
+        let (picked, remaining) = select_packages(pending, 2, |_| true);
+        assert_eq!(hashes(&picked), hashes(&[parent, child]));
+        assert_eq!(hashes(&remaining), hashes(&[other]));
+    }
+
+    #[test]
+    fn keeps_nonce_order_and_skips_packages_that_dont_fit() {
+        let (alice, bob) = (Keypair::new(), Keypair::new());
+        let chain: Vec<SignedTransaction> = (0..3)
+            .map(|nonce| transaction(&alice, nonce, vec![OutPoint::new("coins", nonce)], 10 + 100 * nonce as i64))
+            .collect();
+        let single = transaction(&bob, 0, vec![OutPoint::new("coins", 9)], 40);
+
+        let mut pending = chain.clone();
+        pending.push(single.clone());
+        let (picked, remaining) = select_packages(pending.clone(), 2, |_| true);
+        assert_eq!(hashes(&picked), hashes(&[chain[0].clone(), chain[1].clone()]));
+        assert_eq!(hashes(&remaining), hashes(&[chain[2].clone(), single.clone()]));
+
+        let (picked, _) = select_packages(pending, 4, |_| true);
+        assert_eq!(hashes(&picked), hashes(&[chain[0].clone(), chain[1].clone(), chain[2].clone(), single]));
+    }
+
+    #[test]
+    fn drops_invalid_transactions_and_their_spenders() {
+        let (alice, bob) = (Keypair::new(), Keypair::new());
+        let invalid = transaction(&alice, 0, vec![OutPoint::new("coins", 0)], 10);
+        let spender = transaction(&bob, 0, vec![invalid.outpoint(0)], 10);
+        let next = transaction(&alice, 1, vec![OutPoint::new("coins", 1)], 10);
+
+        let mut spent = HashSet::new();
+        let pending = vec![invalid.clone(), spender, next.clone()];
+        let (picked, remaining) = select_packages(pending, 10, |transaction| {
+            transaction.hash != invalid.hash
+                && transaction.transaction.inputs.iter().all(|input| input.hash != invalid.hash && spent.insert(input.clone()))
+        });
+        assert_eq!(hashes(&picked), hashes(&[next]));
+        assert!(remaining.is_empty());
+    }
+
+    #[test]
+    fn orders_parents_first() {
+        let (alice, bob) = (Keypair::new(), Keypair::new());
+        let parent = transaction(&alice, 0, vec![OutPoint::new("coins", 0)], 10);
+        let child = transaction(&bob, 0, vec![parent.outpoint(0)], 10);
+        let first = transaction(&alice, 1, vec![OutPoint::new("coins", 1)], 10);
+        let unrelated = transaction(&bob, 1, vec![OutPoint::new("coins", 2)], 10);
+
+        let graph = PoolGraph::new(&[child, first, parent, unrelated]);
+        assert_eq!(graph.parents, vec![vec![2], vec![2], vec![], vec![0]]);
+        assert_eq!(graph.order(), vec![2, 0, 1, 3]);
+    }
+
+    #[test]
+    fn leaves_out_cycles() {
+        let alice = Keypair::new();
+        let first = transaction(&alice, 1, vec![OutPoint::new("coins", 0)], 10);
+        let second = transaction(&alice, 0, vec![first.outpoint(0)], 10);
+        let (picked, remaining) = select_packages(vec![first, second], 10, |_| true);
+        assert!(picked.is_empty() && remaining.is_empty());
+    }
+}
@@ -1,209 +1,1062 @@
 use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
 
 pub use crate::keypair::Keypair;
-pub use crate::transaction::Transaction;
+pub use crate::transaction::{LockTime, OutPoint, Output, Transaction};
 pub use crate::signed_transaction::SignedTransaction;
 pub use crate::block::Block;
+pub use crate::block_header::BlockHeader;
+use crate::target::U256;
+use crate::merkle::{self, MerkleProof};
+use crate::block_template::{self, PoolGraph};
+use crate::error::{BlockError, ChainImportError, ConfigError};
+use crate::network_time::NetworkTime;
+use crate::chain_params::ChainParams;
+use crate::genesis::Genesis;
+use crate::utxo_set::{BlockUndo, Utxo, UtxoSet, UtxoView};
 
 pub struct Blockchain {
     genesis_hash: String,
-    blocks: Vec<Block>,
-    pending_transactions: RefCell<Vec<SignedTransaction>>
+    // Every known block keyed by hash, including blocks on side chains
+    blocks: HashMap<String, Block>,
+    // Cumulative proof of work for the chain ending at each block
+    chain_work: HashMap<String, U256>,
+    // Hashes of blocks that no other known block builds on
+    tips: HashSet<String>,
+    // Hashes of the blocks in the active chain, ordered by index
+    active_chain: Vec<String>,
+    // Unspent outputs at the tip of the active chain
+    utxos: UtxoSet,
+    // What each block in the active chain replaced, needed to disconnect it
+    undo: HashMap<String, BlockUndo>,
+    // Hashes of the blocks in the active chain that committed each data payload,
+    // in chain order
+    data_index: HashMap<Vec<u8>, Vec<String>>,
+    pending_transactions: RefCell<Vec<SignedTransaction>>,
+    network_time: NetworkTime,
+    params: ChainParams
 }
 
-// TODO: implement forked chain repair
 impl<'a> Blockchain {
+    // Most pending transactions a single replacement may evict, see
+    // add_pending_transaction
+    pub const MAX_REPLACEMENTS: usize = 100;
+    // Fewest transactions worth verifying on a thread of their own, see
+    // verify_transactions
+    pub const MIN_VERIFY_BATCH: usize = 16;
+
     // Creates a new blockchain with a genesis block
-    // 
-    // Current implementation uses an existing keypair for some initial coins to test with
-    pub fn new(keypair: &Keypair) -> Blockchain {
-        let mut blocks = Vec::new();
+    //
+    // Current implementation uses an existing keypair for the genesis allocation
+    // to test with. A genesis_allocation of 0 allocates nothing.
+    pub fn new(keypair: &Keypair, params: ChainParams) -> Result<Blockchain, ConfigError> {
+        let mut genesis = Genesis::new(0, "");
+        if params.genesis_allocation > 0 {
+            genesis = genesis.allocate(&keypair.export_public_key(), params.genesis_allocation);
+        }
+        Blockchain::with_genesis(&genesis, params)
+    }
 
-        let transaction = Transaction::create(&keypair.export_public_key(), "0", 100);
-        let signed_transaction = SignedTransaction::create(transaction, &keypair);
+    // Creates a new blockchain from a genesis description, see Genesis
+    pub fn with_genesis(genesis: &Genesis, params: ChainParams) -> Result<Blockchain, ConfigError> {
+        params.validate()?;
+        genesis.validate(&params)?;
 
-        let mut transactions = Vec::new();
-        transactions.push(signed_transaction);
-        let block = Block::new(0, transactions, "", 0, 0);
-        let hash = block.as_hash();
+        let block = genesis.build(&params);
+        Ok(Blockchain::from_genesis(block, params))
+    }
 
-        blocks.push(block);
-        Blockchain {
-            genesis_hash: hash,
-            blocks: blocks,
-            pending_transactions: RefCell::new(Vec::new())
+    // Builds a blockchain from an exported sequence of blocks
+    //
+    // The first block is adopted as the genesis block and must be exactly what
+    // Genesis::build makes from a description that passes Genesis::validate,
+    // see Genesis::from_block. Every following block must build on the block
+    // before it and pass validate_block against the ledger built from the blocks
+    // so far. Returns the first block that fails along with its position in the
+    // sequence. Invalid params are reported against the genesis block, since no
+    // block can be checked without them.
+    pub fn from_blocks(blocks: Vec<Block>, params: ChainParams) -> Result<Blockchain, ChainImportError> {
+        if let Err(error) = params.validate() {
+            let hash = blocks.first().map(|block| block.hash.clone()).unwrap_or_default();
+            return Err(ChainImportError { position: 0, hash, error: BlockError::InvalidParams(error.to_string()) });
         }
-    }
 
-    // Runs through all transactions in the ledger to calculate total balance
-    // for an address
-    pub fn calculate_balance(&self, address: &'a str) -> i64 {
-        let mut balance = 0;
-        for block in self.blocks.iter() {
-            for signed_transaction in block.transactions.iter() {
-                if signed_transaction.transaction.from == address {
-                    balance -= signed_transaction.transaction.amount;
-                } else if signed_transaction.transaction.to == address {
-                    balance += signed_transaction.transaction.amount;
-                }
+        let mut blocks = blocks.into_iter().enumerate();
+        let genesis = match blocks.next() {
+            Some((_, block)) => Genesis::from_block(&block)
+                .filter(|genesis| genesis.validate(&params).is_ok())
+                .map(|genesis| genesis.build(&params))
+                .filter(|genesis| genesis.hash == block.hash && block.is_valid())
+                .ok_or(ChainImportError { position: 0, hash: block.hash, error: BlockError::InvalidGenesis })?,
+            None => return Err(ChainImportError { position: 0, hash: String::new(), error: BlockError::InvalidGenesis })
+        };
+        let mut blockchain = Blockchain::from_genesis(genesis, params);
+
+        for (position, block) in blocks {
+            let hash = block.hash.clone();
+            if block.header.previous_hash != *blockchain.best_hash() {
+                return Err(ChainImportError { position, hash, error: BlockError::OutOfOrder });
             }
+            blockchain.add_block(block)
+                .map_err(|error| ChainImportError { position, hash, error })?;
         }
-        balance
+
+        Ok(blockchain)
+    }
+
+    // Returns a copy of the active chain, from the genesis block to the tip
+    //
+    // The result can be loaded elsewhere with from_blocks.
+    pub fn export_blocks(&self) -> Vec<Block> {
+        self.active_chain.iter().map(|hash| self.blocks[hash].clone()).collect()
+    }
+
+    // Returns the headers of the active chain, from the genesis block to the tip,
+    // for light clients to sync from
+    pub fn export_headers(&self) -> Vec<BlockHeader> {
+        self.active_chain.iter().map(|hash| self.blocks[hash].header.clone()).collect()
+    }
+
+    // Sums the unspent outputs sent to an address in the active chain
+    pub fn calculate_balance(&self, address: &'a str) -> i64 {
+        self.utxos.balance(address)
+    }
+
+    // Calculates the part of an address's balance that comes from reward
+    // transactions that can't be spent in the next block yet
+    pub fn calculate_immature_balance(&self, address: &'a str) -> i64 {
+        let next_index = self.best_block().index + 1;
+        self.utxos.outputs_for(address).iter()
+            .filter(|(_, utxo)| !utxo.is_mature(next_index, self.params.reward_maturity))
+            .map(|(_, utxo)| utxo.output.amount)
+            .sum()
+    }
+
+    // Returns the last nonce used by an address in the active chain, or None if
+    // it hasn't sent anything
+    pub fn nonce(&self, address: &'a str) -> Option<u64> {
+        self.utxos.nonce(address)
+    }
+
+    // Returns the nonce a new transaction from an address should use, which is
+    // one above the last nonce used in the active chain or pending pool
+    pub fn next_nonce(&self, address: &'a str) -> u64 {
+        let pending = self.pending_transactions.borrow();
+        pending.iter()
+            .filter(|pending| pending.transaction.from == address)
+            .map(|pending| pending.transaction.nonce)
+            .chain(self.nonce(address))
+            .max()
+            .map_or(0, |nonce| nonce + 1)
+    }
+
+    // Looks up an unspent output in the active chain
+    pub fn utxo(&self, outpoint: &OutPoint) -> Option<&Utxo> {
+        self.utxos.get(outpoint)
+    }
+
+    // Returns the outputs sent to an address that a new transaction can spend
+    //
+    // Includes outputs of pending transactions, and leaves out immature rewards
+    // and outputs already spent by pending transactions.
+    pub fn spendable_outputs(&self, address: &'a str) -> Vec<(OutPoint, Utxo)> {
+        let next_index = self.best_block().index + 1;
+        let median_time_past = self.median_time_past(self.best_block());
+        let pending = self.pending_transactions.borrow();
+        let pending_inputs: HashSet<&OutPoint> = pending.iter()
+            .flat_map(|pending| pending.transaction.inputs.iter())
+            .collect();
+
+        let confirmed = self.utxos.outputs_for(address).into_iter()
+            .filter(|(_, utxo)| utxo.is_mature(next_index, self.params.reward_maturity))
+            .map(|(outpoint, utxo)| (outpoint.clone(), utxo.clone()));
+        let unconfirmed = pending.iter().flat_map(|pending| {
+            pending.transaction.outputs.iter().enumerate()
+                .filter(|(_, output)| output.address == address && !output.is_data())
+                .map(move |(index, output)| {
+                    let utxo = Utxo {
+                        output: output.clone(),
+                        height: next_index,
+                        time: median_time_past,
+                        is_reward: false
+                    };
+                    (pending.outpoint(index as u64), utxo)
+                })
+        });
+
+        confirmed.chain(unconfirmed)
+            .filter(|(outpoint, _)| !pending_inputs.contains(outpoint))
+            .collect()
     }
 
     // Validates a block or errors
     //
     // Checks if the current block is the genesis block, otherwise verifies the blocks
-    // hash. Also checks chain continuity by ensuring the previous hash maps maps to
-    // the block with the next smallest ID.
-    //
-    // TODO: Should the block signature be validated as well?
-    pub fn validate_block(&self, block: &Block) -> Result<(), &str> {
+    // hash. Also checks chain continuity by ensuring the previous hash maps to a known
+    // block with the next smallest ID, that the block carries the expected target and
+    // a hash that meets it, and that its timestamp is sane, see validate_header. Finally every transaction
+    // is checked against the ledger of the chain the block builds on, see
+    // validate_transactions.
+    pub fn validate_block(&self, block: &Block) -> Result<(), BlockError> {
         if !(block.is_valid()) {
-            return Err("Invalid block");
+            return Err(BlockError::InvalidHash);
         }
 
         if block.index == 0 {
             if block.as_hash() == self.genesis_hash {
                 return Ok(());
             } else {
-                return Err("Unable to validate genesis block");
+                return Err(BlockError::InvalidGenesis);
             }
         }
 
-        let previous_block = self.find_block_by_index(block.index - 1);
-        match previous_block {
-            Ok(b) => {
-                if block.previous_hash != b.hash {
-                    return Err("Invalid previous block reference");
-                }
-            },
-            Err(s) => return Err(s)
+        let previous_block = self.find_block_by_hash(&block.header.previous_hash)
+            .map_err(|_| BlockError::UnknownPreviousBlock)?;
+        if block.index != previous_block.index + 1 {
+            return Err(BlockError::InvalidIndex);
+        }
+
+        let median_time_past = self.median_time_past(previous_block);
+        Blockchain::validate_header(&self.params, &block.header, &block.hash, self.next_bits(previous_block), median_time_past, self.adjusted_time())?;
+
+        self.validate_transactions(block)
+    }
+
+    // Validates the header rules that don't need the block body
+    //
+    // Checks the header carries the expected target and a hash that meets it, and
+    // that its timestamp is after the median time past of the previous blocks and
+    // no more than max_future_block_time ahead of network time. Shared with the
+    // light client, which only stores headers.
+    pub fn validate_header(params: &ChainParams, header: &BlockHeader, hash: &str, expected_bits: u32, median_time_past: i64, network_time: i64) -> Result<(), BlockError> {
+        if header.bits != expected_bits {
+            return Err(BlockError::InvalidTarget);
+        }
+
+        if !header.hash_meets_target(hash) {
+            return Err(BlockError::InsufficientProofOfWork);
+        }
+
+        if header.timestamp <= median_time_past {
+            return Err(BlockError::TimestampTooOld);
+        }
+
+        if header.timestamp > network_time.saturating_add(params.max_future_block_time) {
+            return Err(BlockError::TimestampTooFarInFuture);
+        }
+
+        Ok(())
+    }
+
+    // Validates the transactions in a block against the chain it builds on
+    //
+    // The last transaction must be the only reward transaction and mint exactly
+    // the block reward, see block_reward, plus the fees of the other transactions.
+    // The signatures, hashes and scripts of every other transaction are checked
+    // first, in parallel, see verify_transactions. Each must then pass
+    // apply_transaction against the unspent outputs of the chain the block builds
+    // on, after the earlier transactions in the block have been applied.
+    pub fn validate_transactions(&self, block: &Block) -> Result<(), BlockError> {
+        if !self.blocks.contains_key(&block.header.previous_hash) {
+            return Err(BlockError::UnknownPreviousBlock);
+        }
+
+        let (reward, transactions) = block.transactions.split_last()
+            .ok_or(BlockError::MissingReward)?;
+
+        if transactions.len() > self.params.max_transactions {
+            return Err(BlockError::TooManyTransactions);
+        }
+
+        if !reward.is_reward() {
+            return Err(BlockError::MissingReward);
+        }
+
+        let median_time_past = self.median_time_past(&self.blocks[&block.header.previous_hash]);
+        Blockchain::verify_transactions(&self.params, transactions, block.index, median_time_past)?;
+
+        let mut view = self.utxo_view_at(&block.header.previous_hash);
+        for transaction in transactions {
+            self.apply_transaction(&mut view, block.index, median_time_past, transaction, true)?;
+        }
+
+        let fees: i64 = transactions.iter().map(|transaction| transaction.transaction.fee).sum();
+        if !reward.is_valid_reward(block.index, self.block_reward(block.index) + fees) {
+            return Err(BlockError::InvalidReward(reward.hash.clone()));
         }
 
         Ok(())
     }
 
+    // Adds a block to the block tree
+    //
+    // The block may extend any known block, not just the tip of the active chain.
+    // When the chain ending at the new block has more cumulative work than the
+    // active chain, the active chain is reorganized onto it.
+    pub fn add_block(&mut self, block: Block) -> Result<(), BlockError> {
+        if self.blocks.contains_key(&block.hash) {
+            return Err(BlockError::DuplicateBlock);
+        }
+        self.validate_block(&block)?;
+
+        let hash = block.hash.clone();
+        let work = self.chain_work[&block.header.previous_hash].saturating_add(block.work());
+        self.tips.remove(&block.header.previous_hash);
+        self.tips.insert(hash.clone());
+        self.chain_work.insert(hash.clone(), work);
+        self.blocks.insert(hash.clone(), block);
+
+        if work > self.chain_work[self.best_hash()] {
+            self.reorganize(&hash);
+        }
+        Ok(())
+    }
+
     // Adds a transaction to the pending transactions pool
-    pub fn add_pending_transaction(&mut self, transaction: SignedTransaction) {
-        self.pending_transactions.get_mut().push(transaction);
+    //
+    // The transaction must be valid for the next block on the active chain after
+    // the transactions already pending, so it may spend outputs of pending
+    // transactions but not outputs they already spend.
+    //
+    // A transaction that conflicts with pending ones replaces them instead, see
+    // find_replaced. The pool is then put back in dependency order, see
+    // PoolGraph::order, so a replacement is checked after every pending
+    // transaction it spends from and before the sender's later nonces.
+    pub fn add_pending_transaction(&mut self, transaction: SignedTransaction) -> Result<(), BlockError> {
+        let replaced = self.find_replaced(&transaction)?;
+        let next_index = self.best_block().index + 1;
+        let median_time_past = self.median_time_past(self.best_block());
+
+        let mut pool = self.pending_transactions();
+        pool.retain(|pending| !replaced.contains(&pending.hash));
+        pool.push(transaction);
+
+        // A new transaction left out of the order depends on itself, so it goes
+        // last to fail below
+        let mut order = PoolGraph::new(&pool).order();
+        let added = pool.len() - 1;
+        if !order.contains(&added) {
+            order.push(added);
+        }
+        let position = order.iter().position(|index| *index == added).unwrap_or(added);
+        let mut pool: Vec<Option<SignedTransaction>> = pool.into_iter().map(Some).collect();
+        let pool: Vec<SignedTransaction> = order.into_iter().filter_map(|index| pool[index].take()).collect();
+
+        // Transactions already pending were verified when they were added, or
+        // when the pool was last revalidated, so only the new one is verified
+        let mut view = UtxoView::new(&self.utxos);
+        for (index, pending) in pool.iter().enumerate() {
+            if index == position {
+                self.apply_transaction(&mut view, next_index, median_time_past, pending, false)?;
+            } else {
+                let _ = self.apply_transaction(&mut view, next_index, median_time_past, pending, true);
+            }
+        }
+        drop(view);
+
+        *self.pending_transactions.get_mut() = pool;
+        Ok(())
+    }
+
+    // Returns a copy of the transactions waiting to be mined
+    pub fn pending_transactions(&self) -> Vec<SignedTransaction> {
+        self.pending_transactions.borrow().clone()
     }
 
     // Mines a block
     //
     // The current mining process:
     //   1. Make sure theres at least 1 to max transactions per block pending
-    //   2. Validate each transaction
+    //   2. Take pending transactions by the fee per byte of their ancestor
+    //      packages, leaving any that don't fit in the block pending, see
+    //      block_template::select_packages
+    //   3. Validate each transaction, see apply_transaction
     //     a) Verify transaction signature
     //     b) Verify transaction hash
-    //     c) from address contains enough coins
-    //     d) transaction isn't duplicated in pending_transactions
-    //     e) transaction doesn't appear in any other blocks
-    //   3. Create a mining reward transaction
-    //   4. Perform proof of work
-    //   5. Add block to blockchain
-    //
-    // Currently the proof of work consists of finding a hash begining with 4 0's.
-    // This is slightly similar to how bitcoin uses a target to find a smaller hash
-    // but seems much easier to implement. In the future it might be better to switch
-    // back to a byte array and implement an increasing difficulty target.
+    //     c) every input is an unspent, mature output owned by the sender
+    //     d) the inputs add up to the outputs plus fee
+    //   4. Drop any transactions that are invalid
+    //   5. Create a mining reward transaction for the block reward plus fees
+    //   6. Perform proof of work
+    //   7. Add block to the tip of the active chain
+    //
+    // The proof of work consists of finding a hash that, read as a 256 bit number,
+    // is at or below the target encoded in the block bits. The target is adjusted
+    // every retarget_interval blocks, see next_bits.
     //
     // TODO: this was setup just to test mining transactions, need to slightly change
     // this algorithm to take a set of unconfirmed transactions from the FullNode,
     // mine the block, then broadcast the block and update the chain
-    pub fn mine_block(&mut self, reward_address: &str) -> Result<(), &str> {
-        let mut transactions: Vec<SignedTransaction> = Vec::new();
-        // TODO: Replace with longest chain (aka highest id)
-        let latest_block = self.blocks.last().expect("Unknown latest block");
+    pub fn mine_block(&mut self, reward_address: &str) -> Result<(), BlockError> {
+        let latest_block = self.best_block();
         let new_index = latest_block.index + 1;
-        let previous_hash = &latest_block.hash;
+        let previous_hash = latest_block.hash.clone();
+        let bits = self.next_bits(latest_block);
+        let median_time_past = self.median_time_past(latest_block);
+        let timestamp = self.adjusted_time().max(median_time_past + 1);
+
+        let pending = std::mem::take(self.pending_transactions.get_mut());
+        let mut view = UtxoView::new(&self.utxos);
+        // Pending transactions are already verified, see add_pending_transaction
+        let (mut transactions, remaining) = block_template::select_packages(pending, self.params.max_transactions, |transaction| {
+            self.apply_transaction(&mut view, new_index, median_time_past, transaction, true).is_ok()
+        });
+        drop(view);
+        *self.pending_transactions.get_mut() = remaining;
+
+        if transactions.is_empty() {
+            return Err(BlockError::NoTransactions);
+        }
+
+        let fees: i64 = transactions.iter().map(|transaction| transaction.transaction.fee).sum();
+        let reward_transaction = SignedTransaction::create_reward(reward_address, self.block_reward(new_index) + fees, new_index);
+        transactions.push(reward_transaction);
+
+        let mut block = Block::new(new_index, transactions, &previous_hash, timestamp, bits, 0);
+        while !block.meets_target() {
+            block.update_nonce(block.header.nonce + 1)
+        }
+
+        self.add_block(block)
+    }
 
-        for _ in 0..Block::MAX_TRANSACTIONS {
-            let transaction_result = self.pending_transactions.get_mut().pop();
-            if transaction_result.is_none() {
-                // no transactions pending
+    // Records the current time reported by a peer, used to adjust network time,
+    // see NetworkTime::add_sample
+    pub fn add_peer_time(&mut self, peer: &'a str, peer_time: i64) {
+        self.network_time.add_sample(peer, peer_time);
+    }
+
+    // The local clock adjusted by the median offset reported by peers
+    pub fn adjusted_time(&self) -> i64 {
+        self.network_time.now()
+    }
+
+    // Returns the median timestamp of a block and the blocks before it, up to
+    // median_time_span blocks in total. The next block must be newer than this.
+    pub fn median_time_past(&self, block: &Block) -> i64 {
+        let timestamps: Vec<i64> = self.branch(&block.hash)
+            .take(self.params.median_time_span)
+            .map(|block| block.header.timestamp)
+            .collect();
+        median(timestamps)
+    }
+
+    // Returns the number of coins the reward transaction of the block at index
+    // must mint
+    //
+    // The reward starts at initial_reward and halves every halving_interval
+    // blocks, and is cut short once the total supply reaches max_supply.
+    pub fn block_reward(&self, index: u64) -> i64 {
+        if index == 0 {
+            return 0;
+        }
+        self.circulating_supply(index) - self.circulating_supply(index - 1)
+    }
+
+    // Returns the total number of coins in existence once the block at index has
+    // been mined, including the genesis allocation
+    pub fn circulating_supply(&self, index: u64) -> i64 {
+        let mut supply = self.genesis_supply();
+        let mut era: u64 = 0;
+        while era < 63 {
+            let reward = self.params.initial_reward >> era;
+            let start = era.saturating_mul(self.params.halving_interval).max(1);
+            let end = (era + 1).saturating_mul(self.params.halving_interval).saturating_sub(1).min(index);
+            if reward == 0 || start > end {
                 break;
             }
-            let transaction = transaction_result.unwrap();
+            supply = supply.saturating_add(reward.saturating_mul((end - start + 1) as i64));
+            era += 1;
+        }
+        supply.min(self.params.max_supply)
+    }
 
-            if !(transaction.is_valid()) {
-                continue;
-            }
+    // Returns the block at the tip of the active chain
+    pub fn best_block(&self) -> &Block {
+        &self.blocks[self.best_hash()]
+    }
 
-            if self.calculate_balance(&transaction.transaction.from) < transaction.transaction.amount {
-                continue
-            }
+    // Returns every block that is not built on by another known block, including
+    // the tip of the active chain
+    pub fn tips(&self) -> Vec<&Block> {
+        self.tips.iter().map(|hash| &self.blocks[hash]).collect()
+    }
 
-            // TODO: get rid of this O(n^2) bit
-            for t in transactions.iter() {
-                if transaction.hash == t.hash {
-                    continue;
-                }
+    // Returns the cumulative proof of work for the chain ending at a block
+    pub fn chain_work(&self, hash: &str) -> Option<U256> {
+        self.chain_work.get(hash).copied()
+    }
+
+    // Calculates the target bits required for the block built on previous
+    //
+    // Every retarget_interval blocks the target is scaled by how long the last
+    // interval actually took compared to the desired time. The adjustment is
+    // clamped to a factor of 4 either way and never exceeds max_target_bits.
+    pub fn next_bits(&self, previous: &Block) -> u32 {
+        let index = previous.index + 1;
+        if !Blockchain::is_retarget_index(&self.params, index) {
+            return previous.header.bits;
+        }
+
+        let first = self.ancestor(previous, index - self.params.retarget_interval);
+        Blockchain::retarget(&self.params, &previous.header, &first.header)
+    }
+
+    // Checks whether the block at index gets a new target
+    pub fn is_retarget_index(params: &ChainParams, index: u64) -> bool {
+        params.retarget_interval != 0 && index.is_multiple_of(params.retarget_interval)
+    }
+
+    // Scales the target of last by the time taken since first, the header
+    // retarget_interval blocks before the next block
+    pub fn retarget(params: &ChainParams, last: &BlockHeader, first: &BlockHeader) -> u32 {
+        // Saturates rather than overflowing for params that skipped validate
+        let timespan = i64::try_from(params.retarget_interval).unwrap_or(i64::MAX)
+            .saturating_mul(params.target_block_time)
+            .max(1);
+        let actual = last.timestamp.saturating_sub(first.timestamp)
+            .clamp(timespan / 4, timespan.saturating_mul(4));
+
+        let target = last.target().mul_div(actual as u64, timespan as u64);
+        target.min(U256::from_compact(params.max_target_bits)).to_compact()
+    }
+
+    // Returns the consensus parameters for this chain
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    // Returns the hash of the genesis block, which identifies the network
+    pub fn genesis_hash(&self) -> &String {
+        &self.genesis_hash
+    }
+
+    // Finds a block in the active chain by index
+    pub fn find_block_by_index(&self, index: u64) -> Result<&Block, &'static str> {
+        match self.active_chain.get(index as usize) {
+            Some(hash) => Ok(&self.blocks[hash]),
+            None => Err("Unable to find block")
+        }
+    }
+
+    // Finds any known block by hash, including blocks on side chains
+    pub fn find_block_by_hash(&self, hash: &str) -> Result<&Block, &'static str> {
+        self.blocks.get(hash).ok_or("Unable to find block")
+    }
+
+    // Finds the earliest block in the active chain that committed a data payload,
+    // see Transaction::with_data
+    pub fn find_block_by_data(&self, data: &[u8]) -> Result<&Block, &'static str> {
+        match self.data_index.get(data).and_then(|hashes| hashes.first()) {
+            Some(hash) => Ok(&self.blocks[hash]),
+            None => Err("Unable to find block")
+        }
+    }
+
+    // Finds a transaction in the active chain with a given hash
+    //
+    // TODO: Might be able to implement some lookup table or merkle tree
+    pub fn find_transaction_by_hash(&self, hash: &str) -> Result<&SignedTransaction, String> {
+        self.find_transaction_at(hash, self.best_hash())
+            .ok_or_else(|| "No transaction found".to_string())
+    }
+
+    fn from_genesis(block: Block, params: ChainParams) -> Blockchain {
+        let hash = block.as_hash();
+        let work = block.work();
+
+        let mut blockchain = Blockchain {
+            genesis_hash: hash.clone(),
+            blocks: HashMap::new(),
+            chain_work: HashMap::new(),
+            tips: HashSet::new(),
+            active_chain: vec![hash.clone()],
+            utxos: UtxoSet::new(),
+            undo: HashMap::new(),
+            data_index: HashMap::new(),
+            pending_transactions: RefCell::new(Vec::new()),
+            network_time: NetworkTime::new(),
+            params
+        };
+        // The genesis block has no previous blocks, so its outputs count relative
+        // time locks from its own timestamp
+        let mut view = UtxoView::new(&blockchain.utxos);
+        let undo = view.connect_block(&block, block.header.timestamp);
+        let changes = view.into_changes();
+        blockchain.utxos.apply(changes);
+        blockchain.undo.insert(hash.clone(), undo);
+
+        blockchain.chain_work.insert(hash.clone(), work);
+        blockchain.tips.insert(hash.clone());
+        blockchain.blocks.insert(hash, block);
+        blockchain
+    }
+
+    // Builds a proof that a transaction in the active chain is included in its
+    // block, which can be checked with MerkleProof::verify and the block header
+    pub fn merkle_proof(&self, transaction_hash: &str) -> Result<MerkleProof, &'static str> {
+        for hash in self.active_chain.iter() {
+            let block = &self.blocks[hash];
+            let position = block.transactions.iter().position(|transaction| transaction.hash == transaction_hash);
+            if let Some(index) = position {
+                let leaves: Vec<[u8; 32]> = block.transactions.iter()
+                    .map(|transaction| transaction.hash_bytes())
+                    .collect();
+                return Ok(MerkleProof {
+                    transaction_hash: transaction_hash.to_string(),
+                    block_hash: block.hash.clone(),
+                    index,
+                    branch: merkle::merkle_branch(&leaves, index)
+                });
             }
+        }
+        Err("No transaction found")
+    }
+
+    fn genesis_supply(&self) -> i64 {
+        self.blocks[&self.genesis_hash].transactions.iter()
+            .flat_map(|transaction| transaction.transaction.outputs.iter())
+            .map(|output| output.amount)
+            .sum()
+    }
+
+    fn best_hash(&self) -> &String {
+        self.active_chain.last().expect("Unknown latest block")
+    }
 
-            // If found, skip this transaction
-            match self.find_transaction_by_hash(&transaction.hash) {
-                Ok(_) => continue,
-                Err(_) => transactions.push(transaction)
+    // Iterates from a block, which may be on a side chain, back to the genesis block
+    fn branch(&self, tip: &str) -> impl Iterator<Item = &Block> {
+        std::iter::successors(self.blocks.get(tip), move |block| self.blocks.get(&block.header.previous_hash))
+    }
+
+    fn find_transaction_at(&self, hash: &str, tip: &str) -> Option<&SignedTransaction> {
+        self.branch(tip)
+            .flat_map(|block| block.transactions.iter())
+            .find(|transaction| transaction.hash == hash)
+    }
+
+    // Checks a non reward transaction can be included in the block at next_index
+    // and applies it to the view
+    //
+    // There must be between one and max_outputs outputs, each positive except
+    // for at most one data output with a payload of up to max_data_size bytes,
+    // and the fee must be non negative. The nonce must be above the sender's last
+    // nonce in the view. There must be at least one input, each an unspent output
+    // in the view, sent to the transaction's sender and mature by next_index, and
+    // the inputs must add up to exactly the total of the outputs plus fee.
+    //
+    // The transaction's lock time and relative lock must have expired at
+    // next_index and median_time_past, the relative lock counting from the block
+    // that created each input. Outputs created by the view itself count as
+    // confirmed at next_index and median_time_past. Transactions from a script
+    // address must pass their scripts at next_index and median_time_past, see
+    // verify_transaction, which is skipped when is_verified is set because the
+    // caller already ran it. Nothing is applied if the transaction is invalid.
+    fn apply_transaction(&self, view: &mut UtxoView, next_index: u64, median_time_past: i64, signed_transaction: &SignedTransaction, is_verified: bool) -> Result<(), BlockError> {
+        let hash = &signed_transaction.hash;
+        let transaction = &signed_transaction.transaction;
+
+        if signed_transaction.is_reward() {
+            return Err(BlockError::UnexpectedReward(hash.clone()));
+        }
+
+        if transaction.outputs.len() > self.params.max_outputs {
+            return Err(BlockError::TooManyOutputs(hash.clone()));
+        }
+
+        let data: Vec<&Output> = transaction.outputs.iter().filter(|output| output.is_data()).collect();
+        let is_valid_data = data.len() <= 1 && data.iter().all(|output| {
+            output.address.is_empty()
+                && output.amount == 0
+                && output.data.as_ref().is_some_and(|data| data.len() <= self.params.max_data_size)
+        });
+        if !is_valid_data {
+            return Err(BlockError::InvalidData(hash.clone()));
+        }
+
+        let cost = match transaction.cost() {
+            Some(cost) if !transaction.outputs.is_empty() && transaction.outputs.iter().all(|output| output.is_data() || output.amount > 0) && transaction.fee >= 0 => cost,
+            _ => return Err(BlockError::InvalidAmount(hash.clone()))
+        };
+
+        if transaction.inputs.is_empty() {
+            return Err(BlockError::MissingInput(hash.clone()));
+        }
+
+        if !is_verified {
+            Blockchain::verify_transaction(&self.params, signed_transaction, next_index, median_time_past)?;
+        }
+
+        if let Some(LockTime::Time(lock)) = transaction.relative_lock {
+            if lock < 0 {
+                return Err(BlockError::InvalidLock(hash.clone()));
             }
         }
+        if !transaction.is_final(next_index, median_time_past) {
+            return Err(BlockError::TimeLocked(hash.clone()));
+        }
 
-        if transactions.len() <= 0 {
-            return Err("No transactions found");
+        let is_known = (0..transaction.outputs.len())
+            .any(|index| view.get(&signed_transaction.outpoint(index as u64)).is_some());
+        if is_known {
+            return Err(BlockError::DuplicateTransaction(hash.clone()));
         }
 
-        let reward_transaction = SignedTransaction::create_reward(reward_address);
-        transactions.push(reward_transaction);
+        if view.nonce(&transaction.from).is_some_and(|nonce| transaction.nonce <= nonce) {
+            return Err(BlockError::InvalidNonce(hash.clone()));
+        }
 
-        let mut block = Block::create(new_index, transactions, previous_hash, 0);
-        // TODO: This probably could be done with 2 128 byte ints or a byte array
-        // TODO: change target to decrease after x # of blocks making proof harder
-        while &block.hash[0..4] != "0000" {
-            block.update_nonce(block.nonce + 1)
+        let mut spent = HashSet::new();
+        let mut input_total: i64 = 0;
+        let mut is_immature = false;
+        let mut is_locked = false;
+        for input in transaction.inputs.iter() {
+            let utxo = match view.get(input) {
+                Some(utxo) if spent.insert(input) => utxo,
+                _ => return Err(BlockError::MissingInput(hash.clone()))
+            };
+            if utxo.output.address != transaction.from {
+                return Err(BlockError::InvalidInput(hash.clone()));
+            }
+            is_immature |= !utxo.is_mature(next_index, self.params.reward_maturity);
+            is_locked |= transaction.relative_lock
+                .is_some_and(|lock| !lock.is_expired_since(utxo.height, utxo.time, next_index, median_time_past));
+            input_total = input_total.checked_add(utxo.output.amount)
+                .ok_or_else(|| BlockError::InvalidAmount(hash.clone()))?;
         }
 
-        self.blocks.push(block);
-        // TODO: setup increasing reward/decreasing difficulty
+        if input_total < cost {
+            return Err(BlockError::InsufficientBalance(hash.clone()));
+        }
+        if input_total > cost {
+            return Err(BlockError::InvalidAmount(hash.clone()));
+        }
+        if is_immature {
+            return Err(BlockError::ImmatureReward(hash.clone()));
+        }
+        if is_locked {
+            return Err(BlockError::TimeLocked(hash.clone()));
+        }
+
+        for input in transaction.inputs.iter() {
+            view.remove(input);
+        }
+        view.set_nonce(&transaction.from, Some(transaction.nonce));
+        for (index, output) in transaction.outputs.iter().enumerate().filter(|(_, output)| !output.is_data()) {
+            let utxo = Utxo {
+                output: output.clone(),
+                height: next_index,
+                time: median_time_past,
+                is_reward: false
+            };
+            view.insert(signed_transaction.outpoint(index as u64), utxo);
+        }
         Ok(())
     }
 
-    // A really inefficent way to find a block by a particular index
-    fn find_block_by_index(&self, index: u64) -> Result<&Block, &str> {
-        // TODO: Not even sure if this method is needed, 
-        // if so no need to iterate through each block
-        for block in self.blocks.iter() {
-            if block.index == index {
-                return Ok(&block);
+    // Checks the signatures, hash and scripts of a non reward transaction for the
+    // block at next_index, which don't depend on the unspent outputs
+    //
+    // Reward transactions are left to validate_transactions.
+    fn verify_transaction(params: &ChainParams, signed_transaction: &SignedTransaction, next_index: u64, median_time_past: i64) -> Result<(), BlockError> {
+        let hash = &signed_transaction.hash;
+        if signed_transaction.is_reward() {
+            return Ok(());
+        }
+
+        if !signed_transaction.is_valid(params) {
+            return Err(BlockError::InvalidTransaction(hash.clone()));
+        }
+
+        signed_transaction.verify_script(next_index, median_time_past, params)
+            .map_err(|error| BlockError::InvalidScript(hash.clone(), error))
+    }
+
+    // Runs verify_transaction for every transaction of a block in parallel
+    //
+    // The transactions are split into one batch per CPU core, and signatures share
+    // one verification context, see keypair::verify_signature. Returns the error
+    // of the first invalid transaction in block order.
+    fn verify_transactions(params: &ChainParams, transactions: &[SignedTransaction], next_index: u64, median_time_past: i64) -> Result<(), BlockError> {
+        let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        let batch_size = transactions.len().div_ceil(threads).max(Blockchain::MIN_VERIFY_BATCH);
+        if transactions.len() <= batch_size {
+            return transactions.iter()
+                .try_for_each(|transaction| Blockchain::verify_transaction(params, transaction, next_index, median_time_past));
+        }
+
+        let results: Vec<Result<(), BlockError>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = transactions.chunks(batch_size)
+                .map(|batch| scope.spawn(move || {
+                    batch.iter()
+                        .try_for_each(|transaction| Blockchain::verify_transaction(params, transaction, next_index, median_time_past))
+                }))
+                .collect();
+            handles.into_iter()
+                .map(|handle| handle.join().expect("Signature verification thread panicked"))
+                .collect()
+        });
+        results.into_iter().collect()
+    }
+
+    // Works out which pending transactions a new transaction would replace
+    //
+    // A pending transaction conflicts with the new one if it has the same sender
+    // and nonce or spends any of the same inputs. The conflicts are replaced along
+    // with every pending transaction spending their outputs, which would become
+    // invalid. The new transaction must pay a higher fee rate than each conflict
+    // and a higher fee than all the replaced transactions together, and may
+    // replace at most MAX_REPLACEMENTS transactions.
+    fn find_replaced(&self, signed_transaction: &SignedTransaction) -> Result<HashSet<String>, BlockError> {
+        let hash = &signed_transaction.hash;
+        let transaction = &signed_transaction.transaction;
+        let pending = self.pending_transactions.borrow();
+        if pending.iter().any(|pending| pending.hash == *hash) {
+            return Err(BlockError::DuplicateTransaction(hash.clone()));
+        }
+
+        let inputs: HashSet<&OutPoint> = transaction.inputs.iter().collect();
+        let conflicts: Vec<&SignedTransaction> = pending.iter()
+            .filter(|pending| {
+                (pending.transaction.from == transaction.from && pending.transaction.nonce == transaction.nonce)
+                    || pending.transaction.inputs.iter().any(|input| inputs.contains(input))
+            })
+            .collect();
+        if conflicts.iter().any(|conflict| signed_transaction.cmp_fee_rate(conflict) != Ordering::Greater) {
+            return Err(BlockError::InsufficientReplacementFee(hash.clone()));
+        }
+
+        // The pool isn't kept in dependency order after a reorganization, so look
+        // for descendants until there are no more
+        let mut replaced: HashSet<String> = conflicts.iter().map(|conflict| conflict.hash.clone()).collect();
+        loop {
+            let descendants: Vec<String> = pending.iter()
+                .filter(|pending| !replaced.contains(&pending.hash))
+                .filter(|pending| pending.transaction.inputs.iter().any(|input| replaced.contains(&input.hash)))
+                .map(|pending| pending.hash.clone())
+                .collect();
+            if descendants.is_empty() {
+                break;
             }
+            replaced.extend(descendants);
+        }
+
+        if replaced.len() > Blockchain::MAX_REPLACEMENTS {
+            return Err(BlockError::TooManyReplacements(hash.clone()));
+        }
+
+        let replaced_fees = pending.iter()
+            .filter(|pending| replaced.contains(&pending.hash))
+            .fold(0i64, |total, pending| total.saturating_add(pending.transaction.fee));
+        if !replaced.is_empty() && transaction.fee <= replaced_fees {
+            return Err(BlockError::InsufficientReplacementFee(hash.clone()));
+        }
+
+        Ok(replaced)
+    }
+
+    // Builds a view of the unspent outputs at the end of the chain ending at tip,
+    // which may be on a side chain
+    //
+    // Active blocks above the fork point are disconnected in the view using their
+    // undo data and then the side chain blocks are connected in order.
+    fn utxo_view_at(&self, tip: &str) -> UtxoView<'_> {
+        let mut view = UtxoView::new(&self.utxos);
+
+        let mut branch = Vec::new();
+        let mut cursor = &self.blocks[tip];
+        while !self.is_active(&cursor.hash) {
+            branch.push(cursor);
+            cursor = &self.blocks[&cursor.header.previous_hash];
         }
 
-        Err("Unable to find block")
+        for hash in self.active_chain[cursor.index as usize + 1..].iter().rev() {
+            view.disconnect_block(&self.blocks[hash], &self.undo[hash]);
+        }
+        for block in branch.into_iter().rev() {
+            let previous = &self.blocks[&block.header.previous_hash];
+            view.connect_block(block, self.median_time_past(previous));
+        }
+        view
     }
 
-    // A really inefficent way to find a block by a particular hash (currently unused)
-    // fn find_block_by_hash(&self, hash: &str) -> Result<&Block, String> {
-    //     // TODO: Not even sure if this method is needed, 
-    //     // if so could be replaced with a merkle tree
-    //     for block in self.blocks.iter() {
-    //         if block.hash == hash {
-    //             return Ok(&block);
-    //         }
-    //     }
+    // Walks back from a block, which may be on a side chain, to its ancestor at index
+    fn ancestor<'b>(&'b self, block: &'b Block, index: u64) -> &'b Block {
+        let mut cursor = block;
+        while cursor.index > index {
+            cursor = &self.blocks[&cursor.header.previous_hash];
+        }
+        cursor
+    }
 
-    //     Err("Unable to find block".to_string())
-    // }
+    fn is_active(&self, hash: &str) -> bool {
+        let block = &self.blocks[hash];
+        self.active_chain.get(block.index as usize).map(String::as_str) == Some(hash)
+    }
 
-    // Finds a transaction in the ledget with a given hash
+    // Switches the active chain to end at new_tip
     //
-    // TODO: Might be able to implement some lookup table or merkle tree
-    fn find_transaction_by_hash(&self, hash: &str) -> Result<&SignedTransaction, String> {
-        for block in self.blocks.iter() {
-            for transaction in block.transactions.iter() {
-                if transaction.hash == hash {
-                    return Ok(&transaction);
+    // Walks back from the new tip to the fork point, disconnects the active blocks
+    // above the fork and then connects the new branch in order. Finally drops
+    // pending transactions the new chain invalidated, see revalidate_pending.
+    fn reorganize(&mut self, new_tip: &str) {
+        let previous_index = self.best_block().index;
+        let previous_time = self.median_time_past(self.best_block());
+
+        let mut branch = Vec::new();
+        let mut cursor = new_tip.to_string();
+        while !self.is_active(&cursor) {
+            let previous_hash = self.blocks[&cursor].header.previous_hash.clone();
+            branch.push(cursor);
+            cursor = previous_hash;
+        }
+
+        let fork_index = self.blocks[&cursor].index as usize;
+        while self.active_chain.len() > fork_index + 1 {
+            let hash = self.active_chain.pop().expect("Unknown latest block");
+            self.disconnect_block(&hash);
+        }
+
+        for hash in branch.into_iter().rev() {
+            self.connect_block(hash);
+        }
+
+        // Script time locks that passed before still pass unless the tip went back
+        // in height or time, which only a reorganization onto a shorter chain does
+        let is_verified = self.best_block().index >= previous_index
+            && self.median_time_past(self.best_block()) >= previous_time;
+        self.revalidate_pending(is_verified);
+    }
+
+    // Replays the pending pool against the new tip of the active chain, dropping
+    // transactions it no longer allows, such as double spends of coins the new
+    // chain spent or reused nonces, along with every transaction spending their
+    // outputs
+    //
+    // Signatures and scripts are only checked again if is_verified is false, see
+    // apply_transaction.
+    fn revalidate_pending(&mut self, is_verified: bool) {
+        let next_index = self.best_block().index + 1;
+        let median_time_past = self.median_time_past(self.best_block());
+        let pool = std::mem::take(self.pending_transactions.get_mut());
+
+        let mut view = UtxoView::new(&self.utxos);
+        let mut dropped = HashSet::new();
+        let mut kept = Vec::new();
+        for pending in pool {
+            let is_orphaned = pending.transaction.inputs.iter().any(|input| dropped.contains(&input.hash));
+            if !is_orphaned && self.apply_transaction(&mut view, next_index, median_time_past, &pending, is_verified).is_ok() {
+                kept.push(pending);
+            } else {
+                dropped.insert(pending.hash.clone());
+            }
+        }
+        drop(view);
+
+        *self.pending_transactions.get_mut() = kept;
+    }
+
+    // Reverts a block's changes to the unspent outputs and returns its
+    // transactions to the pending pool so they can be mined again
+    //
+    // Blocks are disconnected from the tip down, so the transactions go to the
+    // front of the pool to stay ahead of everything spending their outputs.
+    fn disconnect_block(&mut self, hash: &str) {
+        let block = &self.blocks[hash];
+        let undo = self.undo.remove(hash).expect("Missing undo data for active block");
+        let mut view = UtxoView::new(&self.utxos);
+        view.disconnect_block(block, &undo);
+        let changes = view.into_changes();
+        self.utxos.apply(changes);
+
+        for data in block.transactions.iter().filter_map(|transaction| transaction.transaction.data()) {
+            if let Some(hashes) = self.data_index.get_mut(data) {
+                hashes.retain(|committed| committed != hash);
+                if hashes.is_empty() {
+                    self.data_index.remove(data);
                 }
             }
         }
-        Err("No transaction found".to_string())
+
+        let transactions: Vec<SignedTransaction> = block.transactions.iter()
+            .filter(|transaction| !transaction.is_reward())
+            .cloned()
+            .collect();
+        self.pending_transactions.get_mut().splice(0..0, transactions);
+    }
+
+    // Appends a block to the active chain, applies it to the unspent outputs,
+    // indexes its data payloads and drops its transactions from the pending pool
+    fn connect_block(&mut self, hash: String) {
+        let block = &self.blocks[&hash];
+        let median_time_past = self.median_time_past(&self.blocks[&block.header.previous_hash]);
+        let mut view = UtxoView::new(&self.utxos);
+        let undo = view.connect_block(block, median_time_past);
+        let changes = view.into_changes();
+        self.utxos.apply(changes);
+        self.undo.insert(hash.clone(), undo);
+
+        for data in block.transactions.iter().filter_map(|transaction| transaction.transaction.data()) {
+            self.data_index.entry(data.to_vec()).or_default().push(hash.clone());
+        }
+
+        self.pending_transactions.get_mut()
+            .retain(|pending| !block.transactions.iter().any(|t| t.hash == pending.hash));
+        self.active_chain.push(hash);
+    }
+}
+
+// Returns the median of a list of timestamps, or 0 if the list is empty
+pub fn median(mut timestamps: Vec<i64>) -> i64 {
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+
+    #[test]
+    fn exported_chain_imports_again() {
+        let wallet = Wallet::new();
+        for allocation in [0, 100].iter() {
+            let params = ChainParams { genesis_allocation: *allocation, ..ChainParams::regtest() };
+            let mut blockchain = Blockchain::new(&wallet.keypair, params.clone()).unwrap();
+            if *allocation > 0 {
+                wallet.send(&mut blockchain, "recipient", 10, 1).unwrap();
+                blockchain.mine_block("miner").unwrap();
+            }
+
+            let imported = Blockchain::from_blocks(blockchain.export_blocks(), params).unwrap();
+            assert_eq!(imported.best_hash(), blockchain.best_hash());
+        }
+    }
+
+    #[test]
+    fn replacement_can_spend_later_pending_outputs() {
+        let (sender, payer) = (Wallet::new(), Wallet::new());
+        let address = sender.keypair.export_public_key();
+        let genesis = Genesis::new(0, "")
+            .allocate(&address, 1000)
+            .allocate(&payer.keypair.export_public_key(), 1000);
+        let mut blockchain = Blockchain::with_genesis(&genesis, ChainParams::regtest()).unwrap();
+
+        let original = sender.send(&mut blockchain, "recipient", 10, 1).unwrap();
+        let payment = payer.send(&mut blockchain, &address, 50, 1).unwrap();
+
+        let mut inputs = original.transaction.inputs.clone();
+        inputs.push(payment.outpoint(0));
+        let outputs = vec![Output::new("recipient", 10), Output::new(&address, 1035)];
+        let transaction = Transaction::create(&address, original.transaction.nonce, inputs, outputs, 5);
+        let replacement = SignedTransaction::create(transaction, &sender.keypair);
+        blockchain.add_pending_transaction(replacement.clone()).unwrap();
+
+        let pending: Vec<String> = blockchain.pending_transactions().into_iter().map(|pending| pending.hash).collect();
+        assert_eq!(pending, vec![payment.hash, replacement.hash]);
+        blockchain.mine_block("miner").unwrap();
+        assert_eq!(blockchain.calculate_balance(&address), 1035);
     }
 }
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::convert::TryFrom;
+use std::path::Path;
+
+use crate::error::ConfigError;
+use crate::target::U256;
+
+// Consensus parameters for a network
+//
+// Every node on a network must use the same parameters. Use one of the presets
+// or load custom parameters from a JSON file with from_file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainParams {
+    pub name: String,
+    // Target for the genesis block and the easiest target allowed, in compact form
+    pub max_target_bits: u32,
+    // Number of blocks between difficulty adjustments, 0 disables retargeting
+    pub retarget_interval: u64,
+    // Desired number of seconds between blocks
+    pub target_block_time: i64,
+    // How many seconds ahead of network time a block timestamp may be
+    pub max_future_block_time: i64,
+    // Number of previous blocks whose median timestamp a new block must exceed
+    pub median_time_span: usize,
+    // Maximum number of transactions in a block, not counting the reward
+    pub max_transactions: usize,
+    // Maximum number of recipients a single transaction can pay
+    pub max_outputs: usize,
+    // Maximum size in bytes of a transaction's data payload
+    pub max_data_size: usize,
+    // Maximum number of ops in a script, items on the script stack and bytes in
+    // a single push, see script::execute
+    pub max_script_ops: usize,
+    pub max_stack_size: usize,
+    pub max_push_size: usize,
+    // Maximum number of public keys in a multisig policy
+    pub max_multisig_keys: usize,
+    // Coins minted by the first block reward, halved every halving_interval blocks
+    pub initial_reward: i64,
+    pub halving_interval: u64,
+    // Total coins that can ever exist, including the genesis allocation
+    pub max_supply: i64,
+    // Number of confirmations, counting the including block, before coins from a
+    // reward transaction can be spent
+    pub reward_maturity: u64,
+    // Coins allocated in the genesis block
+    pub genesis_allocation: i64
+}
+
+impl ChainParams {
+    // Largest reward_maturity and max_future_block_time allowed, which keeps the
+    // heights and timestamps they are added to well clear of overflowing
+    pub const MAX_REWARD_MATURITY: u64 = u32::MAX as u64;
+    pub const MAX_FUTURE_BLOCK_TIME: i64 = i32::MAX as i64;
+
+    pub fn mainnet() -> ChainParams {
+        ChainParams {
+            name: "mainnet".to_string(),
+            max_target_bits: 0x1f00ffff,
+            retarget_interval: 10,
+            target_block_time: 60,
+            max_future_block_time: 2 * 60 * 60,
+            median_time_span: 11,
+            max_transactions: 100,
+            max_outputs: 250,
+            max_data_size: 80,
+            max_script_ops: 200,
+            max_stack_size: 100,
+            max_push_size: 520,
+            max_multisig_keys: 15,
+            initial_reward: 10,
+            halving_interval: 1000,
+            max_supply: 15_000,
+            reward_maturity: 10,
+            genesis_allocation: 100
+        }
+    }
+
+    // A public test network with an easier minimum difficulty and faster blocks
+    pub fn testnet() -> ChainParams {
+        ChainParams {
+            name: "testnet".to_string(),
+            max_target_bits: 0x1f0fffff,
+            retarget_interval: 20,
+            target_block_time: 30,
+            reward_maturity: 5,
+            ..ChainParams::mainnet()
+        }
+    }
+
+    // A local network for testing where blocks can be mined almost instantly
+    //
+    // The target accepts roughly every other hash and is never adjusted.
+    pub fn regtest() -> ChainParams {
+        ChainParams {
+            name: "regtest".to_string(),
+            max_target_bits: 0x207fffff,
+            retarget_interval: 0,
+            halving_interval: 150,
+            reward_maturity: 2,
+            ..ChainParams::mainnet()
+        }
+    }
+
+    // Looks up a preset by name
+    pub fn preset(name: &str) -> Option<ChainParams> {
+        match name {
+            "mainnet" => Some(ChainParams::mainnet()),
+            "testnet" => Some(ChainParams::testnet()),
+            "regtest" => Some(ChainParams::regtest()),
+            _ => None
+        }
+    }
+
+    // Loads custom parameters from a JSON file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<ChainParams, ConfigError> {
+        let contents = fs::read_to_string(path)?;
+        let params: ChainParams = serde_json::from_str(&contents)?;
+        params.validate()?;
+        Ok(params)
+    }
+
+    // Checks the parameters can be used to run a chain
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let max_target = U256::from_compact(self.max_target_bits);
+        if max_target == U256::ZERO || max_target.bits() > 255 {
+            return Err(ConfigError::Invalid("max_target_bits must decode to a non zero target below 2^255"));
+        }
+        if self.target_block_time <= 0 || self.max_future_block_time < 0 {
+            return Err(ConfigError::Invalid("block times must be positive"));
+        }
+        if self.max_future_block_time > ChainParams::MAX_FUTURE_BLOCK_TIME || self.reward_maturity > ChainParams::MAX_REWARD_MATURITY {
+            return Err(ConfigError::Invalid("max_future_block_time and reward_maturity must not exceed 2^31 - 1 and 2^32 - 1"));
+        }
+        // retarget clamps the time taken to four times the retarget timespan
+        let retarget_limit = i64::try_from(self.retarget_interval).ok()
+            .and_then(|interval| interval.checked_mul(self.target_block_time))
+            .and_then(|timespan| timespan.checked_mul(4));
+        if retarget_limit.is_none() {
+            return Err(ConfigError::Invalid("target_block_time times retarget_interval times 4 must fit in 64 bits"));
+        }
+        if self.median_time_span == 0 || self.max_transactions == 0 || self.max_outputs == 0 || self.halving_interval == 0 {
+            return Err(ConfigError::Invalid("median_time_span, max_transactions, max_outputs and halving_interval must be positive"));
+        }
+        if self.max_script_ops == 0 || self.max_stack_size == 0 || self.max_push_size == 0 || self.max_multisig_keys == 0 {
+            return Err(ConfigError::Invalid("max_script_ops, max_stack_size, max_push_size and max_multisig_keys must be positive"));
+        }
+        if self.initial_reward < 0 || self.genesis_allocation < 0 || self.genesis_allocation > self.max_supply {
+            return Err(ConfigError::Invalid("rewards and genesis_allocation must be between 0 and max_supply"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ChainParams {
+    fn default() -> ChainParams {
+        ChainParams::mainnet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid() {
+        for name in ["mainnet", "testnet", "regtest"].iter() {
+            assert!(ChainParams::preset(name).unwrap().validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_values_that_overflow() {
+        let params = ChainParams { reward_maturity: u64::MAX, ..ChainParams::regtest() };
+        assert!(params.validate().is_err());
+
+        let params = ChainParams { max_future_block_time: i64::MAX, ..ChainParams::regtest() };
+        assert!(params.validate().is_err());
+
+        let params = ChainParams { target_block_time: i64::MAX / 2, retarget_interval: 2, ..ChainParams::mainnet() };
+        assert!(params.validate().is_err());
+
+        let params = ChainParams { target_block_time: i64::MAX / 8, retarget_interval: 1, ..ChainParams::mainnet() };
+        assert!(params.validate().is_ok());
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+
+// Reasons a block can be rejected
+//
+// Variants that concern a single transaction carry that transaction's hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockError {
+    DuplicateBlock,
+    InvalidHash,
+    InvalidGenesis,
+    UnknownPreviousBlock,
+    OutOfOrder,
+    InvalidIndex,
+    InvalidTarget,
+    InsufficientProofOfWork,
+    TimestampTooOld,
+    TimestampTooFarInFuture,
+    NoTransactions,
+    TooManyTransactions,
+    MissingReward,
+    UnexpectedReward(String),
+    InvalidReward(String),
+    InvalidTransaction(String),
+    InvalidAmount(String),
+    InsufficientBalance(String),
+    ImmatureReward(String),
+    DuplicateTransaction(String),
+    MissingInput(String),
+    InvalidInput(String),
+    InvalidNonce(String),
+    TooManyOutputs(String),
+    TimeLocked(String),
+    InvalidLock(String),
+    InsufficientReplacementFee(String),
+    TooManyReplacements(String),
+    InvalidData(String),
+    InvalidParams(String),
+    InvalidScript(String, ScriptError)
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BlockError::DuplicateBlock => fmt.write_str("Block already known"),
+            BlockError::InvalidHash => fmt.write_str("Invalid block hash"),
+            BlockError::InvalidGenesis => fmt.write_str("Unable to validate genesis block"),
+            BlockError::UnknownPreviousBlock => fmt.write_str("Unknown previous block"),
+            BlockError::OutOfOrder => fmt.write_str("Block does not build on the block before it"),
+            BlockError::InvalidIndex => fmt.write_str("Invalid previous block reference"),
+            BlockError::InvalidTarget => fmt.write_str("Invalid difficulty target"),
+            BlockError::InsufficientProofOfWork => fmt.write_str("Insufficient proof of work"),
+            BlockError::TimestampTooOld => fmt.write_str("Timestamp is not after the median time of recent blocks"),
+            BlockError::TimestampTooFarInFuture => fmt.write_str("Timestamp is too far in the future"),
+            BlockError::NoTransactions => fmt.write_str("No transactions found"),
+            BlockError::TooManyTransactions => fmt.write_str("Too many transactions"),
+            BlockError::MissingReward => fmt.write_str("Missing reward transaction"),
+            BlockError::UnexpectedReward(hash) => write!(fmt, "Unexpected reward transaction {}", hash),
+            BlockError::InvalidReward(hash) => write!(fmt, "Invalid reward transaction {}", hash),
+            BlockError::InvalidTransaction(hash) => write!(fmt, "Invalid signature or hash for transaction {}", hash),
+            BlockError::InvalidAmount(hash) => write!(fmt, "Invalid amount or fee for transaction {}", hash),
+            BlockError::InsufficientBalance(hash) => write!(fmt, "Insufficient balance for transaction {}", hash),
+            BlockError::ImmatureReward(hash) => write!(fmt, "Transaction {} spends immature reward coins", hash),
+            BlockError::DuplicateTransaction(hash) => write!(fmt, "Duplicate transaction {}", hash),
+            BlockError::MissingInput(hash) => write!(fmt, "Transaction {} spends a missing or already spent output", hash),
+            BlockError::InvalidInput(hash) => write!(fmt, "Transaction {} spends an output that wasn't sent to its sender", hash),
+            BlockError::InvalidNonce(hash) => write!(fmt, "Transaction {} doesn't have a nonce above its sender's last nonce", hash),
+            BlockError::TooManyOutputs(hash) => write!(fmt, "Too many outputs in transaction {}", hash),
+            BlockError::TimeLocked(hash) => write!(fmt, "Transaction {} is time locked until a later block", hash),
+            BlockError::InvalidLock(hash) => write!(fmt, "Transaction {} has a negative relative time lock", hash),
+            BlockError::InsufficientReplacementFee(hash) => write!(fmt, "Transaction {} doesn't pay enough to replace the pending transactions it conflicts with", hash),
+            BlockError::TooManyReplacements(hash) => write!(fmt, "Transaction {} would replace too many pending transactions", hash),
+            BlockError::InvalidData(hash) => write!(fmt, "Invalid or oversized data payload in transaction {}", hash),
+            BlockError::InvalidParams(reason) => fmt.write_str(reason),
+            BlockError::InvalidScript(hash, error) => write!(fmt, "Script failed for transaction {}: {}", hash, error)
+        }
+    }
+}
+
+impl std::error::Error for BlockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BlockError::InvalidScript(_, error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+// Reasons a script can fail, see script::verify
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptError {
+    MissingScript,
+    WrongScript,
+    NotPushOnly,
+    TooManyOps,
+    PushTooLarge,
+    StackUnderflow,
+    StackOverflow,
+    UnbalancedConditional,
+    InvalidNumber,
+    VerifyFailed,
+    Return,
+    Locked,
+    FalseResult
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::MissingScript => fmt.write_str("Spending a script address needs its scripts"),
+            ScriptError::WrongScript => fmt.write_str("Locking script doesn't match the address"),
+            ScriptError::NotPushOnly => fmt.write_str("Unlocking script may only push data"),
+            ScriptError::TooManyOps => fmt.write_str("Too many ops in script"),
+            ScriptError::PushTooLarge => fmt.write_str("Pushed data is too large"),
+            ScriptError::StackUnderflow => fmt.write_str("Not enough items on the stack"),
+            ScriptError::StackOverflow => fmt.write_str("Too many items on the stack"),
+            ScriptError::UnbalancedConditional => fmt.write_str("Unbalanced If, Else or EndIf"),
+            ScriptError::InvalidNumber => fmt.write_str("Number is out of range"),
+            ScriptError::VerifyFailed => fmt.write_str("Verify failed"),
+            ScriptError::Return => fmt.write_str("Script returned early"),
+            ScriptError::Locked => fmt.write_str("Coins are still time locked"),
+            ScriptError::FalseResult => fmt.write_str("Script finished without a true result")
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+// The first invalid block found while importing a chain, see Blockchain::from_blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainImportError {
+    // Position of the block in the imported sequence
+    pub position: usize,
+    pub hash: String,
+    pub error: BlockError
+}
+
+impl fmt::Display for ChainImportError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Block {} ({}) is invalid: {}", self.position, self.hash, self.error)
+    }
+}
+
+impl std::error::Error for ChainImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+// Reasons a light client can reject a transaction offered with a Merkle proof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    NotWatched,
+    HashMismatch,
+    InvalidTransaction,
+    UnknownBlock,
+    InvalidProof
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofError::NotWatched => fmt.write_str("Transaction doesn't involve a watched address"),
+            ProofError::HashMismatch => fmt.write_str("Proof is for a different transaction"),
+            ProofError::InvalidTransaction => fmt.write_str("Invalid signature or hash for transaction"),
+            ProofError::UnknownBlock => fmt.write_str("Proof is for a block not in the header chain"),
+            ProofError::InvalidProof => fmt.write_str("Merkle proof doesn't match the block header")
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+// Reasons a wallet can't send a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletError {
+    InsufficientFunds,
+    Rejected(BlockError)
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WalletError::InsufficientFunds => fmt.write_str("Not enough spendable coins"),
+            WalletError::Rejected(error) => write!(fmt, "Transaction rejected: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for WalletError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WalletError::InsufficientFunds => None,
+            WalletError::Rejected(error) => Some(error)
+        }
+    }
+}
+
+impl From<BlockError> for WalletError {
+    fn from(error: BlockError) -> WalletError {
+        WalletError::Rejected(error)
+    }
+}
+
+// Reasons a configuration file can't be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    ParseToml(toml::de::Error),
+    Invalid(&'static str)
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(fmt, "Unable to read config: {}", error),
+            ConfigError::Parse(error) => write!(fmt, "Unable to parse config: {}", error),
+            ConfigError::ParseToml(error) => write!(fmt, "Unable to parse config: {}", error),
+            ConfigError::Invalid(reason) => write!(fmt, "Invalid config: {}", reason)
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(error) => Some(error),
+            ConfigError::Parse(error) => Some(error),
+            ConfigError::ParseToml(error) => Some(error),
+            ConfigError::Invalid(_) => None
+        }
+    }
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(error: std::io::Error) -> ConfigError {
+        ConfigError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(error: serde_json::Error) -> ConfigError {
+        ConfigError::Parse(error)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(error: toml::de::Error) -> ConfigError {
+        ConfigError::ParseToml(error)
+    }
+}
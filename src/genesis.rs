@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::block::Block;
+use crate::chain_params::ChainParams;
+use crate::error::ConfigError;
+use crate::signed_transaction::SignedTransaction;
+use crate::transaction::{OutPoint, Output};
+
+// Coins given to an address in the genesis block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Allocation {
+    pub address: String,
+    pub amount: i64
+}
+
+// Describes the first block of a network
+//
+// The genesis block holds a single transaction. Its first output commits the
+// message as a data payload, followed by one output per allocation in order.
+// Every field of the block is derived from this description and the chain
+// params, so the same description always builds a block with the same hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Genesis {
+    pub timestamp: i64,
+    pub message: String,
+    #[serde(default)]
+    pub allocations: Vec<Allocation>
+}
+
+impl<'a> Genesis {
+    pub fn new(timestamp: i64, message: &'a str) -> Genesis {
+        Genesis {
+            timestamp,
+            message: message.to_string(),
+            allocations: Vec::new()
+        }
+    }
+
+    // Adds an allocation, for chaining while building a genesis in code
+    pub fn allocate(mut self, address: &'a str, amount: i64) -> Genesis {
+        self.allocations.push(Allocation {
+            address: address.to_string(),
+            amount
+        });
+        self
+    }
+
+    // Loads a genesis description from a file
+    //
+    // Files ending in .toml are read as TOML, anything else as JSON.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Genesis, ConfigError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let genesis: Genesis = match path.extension() {
+            Some(extension) if extension == "toml" => toml::from_str(&contents)?,
+            _ => serde_json::from_str(&contents)?
+        };
+        Ok(genesis)
+    }
+
+    // Recovers the description a genesis block was built from
+    //
+    // Returns None unless the block holds a single allocation transaction, see
+    // SignedTransaction::create_allocation, whose first output is the message
+    // followed by the allocations. The block must still be checked against what
+    // build makes from the result, since only the outputs and timestamp are read.
+    pub fn from_block(block: &Block) -> Option<Genesis> {
+        let signed_transaction = match block.transactions.as_slice() {
+            [signed_transaction] => signed_transaction,
+            _ => return None
+        };
+        let transaction = &signed_transaction.transaction;
+        let is_allocation = signed_transaction.is_reward()
+            && signed_transaction.signature == "0"
+            && signed_transaction.multisig.is_none()
+            && signed_transaction.script.is_none()
+            && transaction.inputs == [OutPoint::reward(0)];
+        if !is_allocation {
+            return None;
+        }
+
+        let (message, allocations) = transaction.outputs.split_first()?;
+        if !message.address.is_empty() || message.amount != 0 {
+            return None;
+        }
+        let message = String::from_utf8(message.data.clone()?).ok()?;
+        let allocations = allocations.iter()
+            .map(|output| match output.data {
+                None => Some(Allocation { address: output.address.clone(), amount: output.amount }),
+                Some(_) => None
+            })
+            .collect::<Option<Vec<Allocation>>>()?;
+
+        Some(Genesis {
+            timestamp: block.header.timestamp,
+            message,
+            allocations
+        })
+    }
+
+    // Sum of all allocations, or None if it overflows
+    pub fn total_allocation(&self) -> Option<i64> {
+        self.allocations.iter()
+            .try_fold(0i64, |total, allocation| total.checked_add(allocation.amount))
+    }
+
+    // Checks the genesis can start a chain with the given params
+    pub fn validate(&self, params: &ChainParams) -> Result<(), ConfigError> {
+        if self.message.len() > params.max_data_size {
+            return Err(ConfigError::Invalid("message must not exceed max_data_size"));
+        }
+        if self.allocations.iter().any(|allocation| allocation.address.is_empty() || allocation.address == "0") {
+            return Err(ConfigError::Invalid("allocation addresses must not be empty or 0"));
+        }
+        if self.allocations.iter().any(|allocation| allocation.amount <= 0) {
+            return Err(ConfigError::Invalid("allocation amounts must be positive"));
+        }
+        match self.total_allocation() {
+            Some(total) if total <= params.max_supply => Ok(()),
+            _ => Err(ConfigError::Invalid("allocations must not exceed max_supply"))
+        }
+    }
+
+    // Builds the genesis block, using the easiest target allowed by params
+    pub fn build(&self, params: &ChainParams) -> Block {
+        let mut outputs = vec![Output::new_data(self.message.clone().into_bytes())];
+        for allocation in self.allocations.iter() {
+            outputs.push(Output::new(&allocation.address, allocation.amount));
+        }
+
+        let transaction = SignedTransaction::create_allocation(outputs, self.timestamp);
+        Block::new(0, vec![transaction], "", self.timestamp, params.max_target_bits, 0)
+    }
+}
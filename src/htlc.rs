@@ -0,0 +1,99 @@
+use sha2::{Sha256, Digest};
+use std::convert::TryFrom;
+
+use crate::script::{Op, Script};
+use crate::signed_transaction::SignedTransaction;
+use crate::transaction::LockTime;
+
+// A hash time locked contract
+//
+// Coins sent to the contract's address can be claimed by the recipient with
+// the preimage of hash_lock, or refunded to the sender once timeout has passed.
+// Locking coins on two ledgers to the same hash_lock makes an atomic swap, since
+// claiming one side reveals the preimage needed to claim the other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Htlc {
+    // Public key that can refund the coins after timeout
+    pub sender: String,
+    // Public key that can claim the coins with the preimage
+    pub recipient: String,
+    // SHA-256 hash of the preimage
+    pub hash_lock: [u8; 32],
+    pub timeout: LockTime
+}
+
+impl<'a> Htlc {
+    pub fn new(sender: &'a str, recipient: &'a str, hash_lock: [u8; 32], timeout: LockTime) -> Htlc {
+        Htlc {
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            hash_lock,
+            timeout
+        }
+    }
+
+    // Hashes a secret preimage into a hash_lock
+    pub fn hash_preimage(preimage: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.input(preimage);
+        let result = hasher.result();
+
+        let mut hash: [u8; 32] = Default::default();
+        hash.copy_from_slice(result.as_slice());
+        hash
+    }
+
+    // The address coins are locked to, see Script::address
+    pub fn address(&self) -> String {
+        self.locking_script().address()
+    }
+
+    // Claims with a signature from the recipient and the preimage, or refunds
+    // with a signature from the sender after the timeout
+    pub fn locking_script(&self) -> Script {
+        let timeout = match self.timeout {
+            LockTime::Height(height) => vec![Op::Number(i64::try_from(height).unwrap_or(i64::MAX)), Op::CheckHeightVerify],
+            LockTime::Time(time) => vec![Op::Number(time), Op::CheckTimeVerify]
+        };
+
+        let mut ops = vec![
+            Op::If,
+            Op::Sha256, Op::Push(self.hash_lock.to_vec()), Op::EqualVerify,
+            Op::push_str(&self.recipient),
+            Op::Else
+        ];
+        ops.extend(timeout);
+        ops.extend(vec![
+            Op::push_str(&self.sender),
+            Op::EndIf,
+            Op::CheckSig
+        ]);
+        Script::new(ops)
+    }
+
+    // Unlocks the claim branch given the recipient's signature of the spending
+    // transaction
+    pub fn claim_script(&self, signature: &str, preimage: &[u8]) -> Script {
+        Script::new(vec![Op::push_str(signature), Op::Push(preimage.to_vec()), Op::Number(1)])
+    }
+
+    // Unlocks the refund branch given the sender's signature of the spending
+    // transaction
+    pub fn refund_script(&self, signature: &str) -> Script {
+        Script::new(vec![Op::push_str(signature), Op::Number(0)])
+    }
+
+    // Recovers the preimage from a transaction claiming this contract, which the
+    // sender needs to claim the other side of a swap
+    pub fn extract_preimage(&self, signed_transaction: &SignedTransaction) -> Option<Vec<u8>> {
+        let script = signed_transaction.script.as_ref()?;
+        if script.locking != self.locking_script() {
+            return None;
+        }
+
+        match script.unlocking.ops.as_slice() {
+            [Op::Push(_), Op::Push(preimage), Op::Number(1)] if Htlc::hash_preimage(preimage) == self.hash_lock => Some(preimage.clone()),
+            _ => None
+        }
+    }
+}
@@ -1,22 +1,63 @@
 extern crate serde;
 
 use rand::rngs::OsRng;
-use secp256k1::{Secp256k1, SecretKey, PublicKey, Message, Signature};
+use secp256k1::{Secp256k1, SecretKey, PublicKey, Message, KeyPair, XOnlyPublicKey, VerifyOnly};
+use secp256k1::{ecdsa, schnorr};
 use std::str::FromStr;
+use std::sync::OnceLock;
+
+// Verification context shared by every call to verify_signature, since creating
+// one is far slower than verifying a signature
+static VERIFICATION_CONTEXT: OnceLock<Secp256k1<VerifyOnly>> = OnceLock::new();
 
 pub struct Keypair {
     public_key: PublicKey,
     secret_key: SecretKey
 }
 
+// The kinds of signature a key can make
+//
+// Signatures are hex encoded with the version byte first, so verify_signature
+// can tell them apart. Both kinds verify against the same public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureScheme {
+    // DER encoded ECDSA signature
+    Ecdsa,
+    // 64 byte BIP340 Schnorr signature
+    Schnorr
+}
+
+impl SignatureScheme {
+    pub fn version(&self) -> u8 {
+        match self {
+            SignatureScheme::Ecdsa => 0,
+            SignatureScheme::Schnorr => 1
+        }
+    }
+
+    pub fn from_version(version: u8) -> Option<SignatureScheme> {
+        match version {
+            0 => Some(SignatureScheme::Ecdsa),
+            1 => Some(SignatureScheme::Schnorr),
+            _ => None
+        }
+    }
+}
+
+impl Default for Keypair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Keypair {
     pub fn new() -> Keypair {
         let secp = Secp256k1::new();
         let mut rng = OsRng::new().expect("OsRng");
         let (secret_key, public_key) = secp.generate_keypair(&mut rng);
         Keypair {
-            public_key: public_key,
-            secret_key: secret_key
+            public_key,
+            secret_key
         }
     }
 
@@ -25,7 +66,7 @@ impl Keypair {
         let key = SecretKey::from_slice(secret_key).expect("Failed to parse secret key");
         let public_key = PublicKey::from_secret_key(&secp, &key);
         Keypair {
-            public_key: public_key,
+            public_key,
             secret_key: key
         }
     }
@@ -44,34 +85,61 @@ impl Keypair {
 
     // TODO: Set change message to hash
     pub fn sign(&self, message: &[u8]) -> String {
+        self.sign_with(message, SignatureScheme::Ecdsa)
+    }
+
+    // Signs a 32 byte message with the given scheme, see SignatureScheme
+    pub fn sign_with(&self, message: &[u8], scheme: SignatureScheme) -> String {
         let secp = Secp256k1::new();
-        let message_bytes = Message::from_slice(&message).expect("Unable to read Message");
-        let signature = secp.sign(&message_bytes, &self.secret_key);
-        signature.to_string()
+        let message_bytes = Message::from_slice(message).expect("Unable to read Message");
+        let signature = match scheme {
+            SignatureScheme::Ecdsa => secp.sign_ecdsa(&message_bytes, &self.secret_key).serialize_der().to_vec(),
+            SignatureScheme::Schnorr => {
+                let keypair = KeyPair::from_secret_key(&secp, self.secret_key);
+                let mut rng = OsRng::new().expect("OsRng");
+                secp.sign_schnorr_with_rng(&message_bytes, &keypair, &mut rng).as_ref().to_vec()
+            }
+        };
+        hex::encode([vec![scheme.version()], signature].concat())
     }
 
     // TODO: Set change message to hash
     pub fn verify(&self, signature: String, message: &[u8], public_key: Option<String>) -> bool {
-        let secp = Secp256k1::new();
-        let m = Message::from_slice(&message).expect("Unable to read Message");
-        let s = Signature::from_str(&signature).expect("Unable to read Signature");
-        match public_key {
-            None => secp.verify(&m, &s, &self.public_key).is_ok(),
-            Some(key) => {
-                let pk = PublicKey::from_str(&key).expect("Unable to read Public Key");
-                secp.verify(&m, &s, &pk).is_ok()
-            }
-        }
+        let public_key = public_key.unwrap_or_else(|| self.export_public_key());
+        verify_signature(&public_key, &signature, message)
     }
 
 }
 
+// Verifies a signature of either scheme for a message, returning false if any
+// of the inputs can't be parsed
+//
+// Schnorr signatures are checked against the x coordinate of the public key.
+// Safe to call from many threads at once.
 pub fn verify_signature(public_key: &str, signature: &str, message: &[u8]) -> bool {
-        let secp = Secp256k1::new();
-        let k = PublicKey::from_str(public_key).expect("Unable to read Public Key");
-        let m = Message::from_slice(message).expect("Unable to read Message");
-        let s = Signature::from_str(signature).expect("Unable to read Signature");
-        secp.verify(&m, &s, &k).is_ok()
-        
+    let secp = VERIFICATION_CONTEXT.get_or_init(Secp256k1::verification_only);
+    let k = match PublicKey::from_str(public_key) {
+        Ok(k) => k,
+        Err(_) => return false
+    };
+    let m = match Message::from_slice(message) {
+        Ok(m) => m,
+        Err(_) => return false
+    };
+    let bytes = match hex::decode(signature) {
+        Ok(bytes) if !bytes.is_empty() => bytes,
+        _ => return false
+    };
 
-}
\ No newline at end of file
+    match SignatureScheme::from_version(bytes[0]) {
+        Some(SignatureScheme::Ecdsa) => match ecdsa::Signature::from_der(&bytes[1..]) {
+            Ok(s) => secp.verify_ecdsa(&m, &s, &k).is_ok(),
+            Err(_) => false
+        },
+        Some(SignatureScheme::Schnorr) => match schnorr::Signature::from_slice(&bytes[1..]) {
+            Ok(s) => secp.verify_schnorr(&s, &m, &XOnlyPublicKey::from(k)).is_ok(),
+            Err(_) => false
+        },
+        None => false
+    }
+}
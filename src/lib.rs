@@ -11,6 +11,19 @@
 pub mod blockchain;
 pub mod wallet;
 pub mod keypair;
+pub mod multisig;
+pub mod script;
+pub mod htlc;
 pub mod transaction;
 pub mod signed_transaction;
+pub mod utxo_set;
 pub mod block;
+pub mod block_header;
+pub mod block_template;
+pub mod merkle;
+pub mod light_client;
+pub mod network_time;
+pub mod chain_params;
+pub mod genesis;
+pub mod target;
+pub mod error;
@@ -0,0 +1,239 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::block_header::BlockHeader;
+use crate::blockchain::{self, Blockchain};
+use crate::chain_params::ChainParams;
+use crate::error::{BlockError, ProofError};
+use crate::merkle::MerkleProof;
+use crate::network_time::NetworkTime;
+use crate::signed_transaction::SignedTransaction;
+use crate::transaction::OutPoint;
+use crate::target::U256;
+
+struct HeaderEntry {
+    header: BlockHeader,
+    index: u64,
+    // Cumulative proof of work for the chain ending at this header
+    chain_work: U256
+}
+
+// A simplified payment verification client
+//
+// Only block headers are stored. Headers are checked for linkage and proof of
+// work and the chain with the most cumulative work is followed. Transactions
+// are only accepted for watched addresses and only with a Merkle proof tying
+// them to a header in the active chain.
+pub struct LightClient {
+    headers: HashMap<String, HeaderEntry>,
+    // Hashes of the headers in the active chain, ordered by index
+    active_chain: Vec<String>,
+    watched: HashSet<String>,
+    // Verified transactions for watched addresses keyed by the including block hash
+    transactions: HashMap<String, Vec<SignedTransaction>>,
+    network_time: NetworkTime,
+    params: ChainParams
+}
+
+impl<'a> LightClient {
+    // Creates a light client that trusts the given genesis header
+    pub fn new(genesis: BlockHeader, params: ChainParams) -> LightClient {
+        let hash = genesis.as_hash();
+        let entry = HeaderEntry {
+            chain_work: genesis.work(),
+            header: genesis,
+            index: 0
+        };
+
+        let mut headers = HashMap::new();
+        headers.insert(hash.clone(), entry);
+        LightClient {
+            headers,
+            active_chain: vec![hash],
+            watched: HashSet::new(),
+            transactions: HashMap::new(),
+            network_time: NetworkTime::new(),
+            params
+        }
+    }
+
+    // Adds a header that builds on any known header
+    //
+    // Switches the active chain when the new header's chain has more work.
+    pub fn add_header(&mut self, header: BlockHeader) -> Result<(), BlockError> {
+        let hash = header.as_hash();
+        if self.headers.contains_key(&hash) {
+            return Err(BlockError::DuplicateBlock);
+        }
+
+        let previous = self.headers.get(&header.previous_hash)
+            .ok_or(BlockError::UnknownPreviousBlock)?;
+        Blockchain::validate_header(&self.params, &header, &hash, self.next_bits(previous), self.median_time_past(previous), self.network_time.now())?;
+
+        let entry = HeaderEntry {
+            index: previous.index + 1,
+            chain_work: previous.chain_work.saturating_add(header.work()),
+            header
+        };
+        let is_best = entry.chain_work > self.headers[self.best_hash()].chain_work;
+        self.headers.insert(hash.clone(), entry);
+
+        if is_best {
+            self.reorganize(hash);
+        }
+        Ok(())
+    }
+
+    // Adds headers in order, stopping at the first invalid header
+    pub fn add_headers(&mut self, headers: Vec<BlockHeader>) -> Result<(), BlockError> {
+        for header in headers {
+            self.add_header(header)?;
+        }
+        Ok(())
+    }
+
+    // Records the current time reported by a peer, used to adjust network time,
+    // see NetworkTime::add_sample
+    pub fn add_peer_time(&mut self, peer: &'a str, peer_time: i64) {
+        self.network_time.add_sample(peer, peer_time);
+    }
+
+    // Starts accepting transactions sent to or from an address
+    pub fn watch_address(&mut self, address: &'a str) {
+        self.watched.insert(address.to_string());
+    }
+
+    // Accepts a transaction for a watched address along with proof of inclusion
+    // in a block of the header chain
+    pub fn add_transaction(&mut self, transaction: SignedTransaction, proof: &MerkleProof) -> Result<(), ProofError> {
+        let is_watched = self.watched.contains(&transaction.transaction.from)
+            || transaction.transaction.outputs.iter().any(|output| self.watched.contains(&output.address));
+        if !is_watched {
+            return Err(ProofError::NotWatched);
+        }
+
+        if transaction.hash != proof.transaction_hash {
+            return Err(ProofError::HashMismatch);
+        }
+
+        let entry = self.headers.get(&proof.block_hash).ok_or(ProofError::UnknownBlock)?;
+        let is_valid = match transaction.transaction.output_total() {
+            Some(total) if transaction.is_reward() => transaction.is_valid_reward(entry.index, total),
+            _ => transaction.is_valid(&self.params) && self.headers.get(&entry.header.previous_hash)
+                .map(|previous| self.median_time_past(previous))
+                .is_some_and(|time| transaction.transaction.is_final(entry.index, time) && transaction.verify_script(entry.index, time, &self.params).is_ok())
+        };
+        if !is_valid {
+            return Err(ProofError::InvalidTransaction);
+        }
+
+        if !proof.verify(&entry.header) {
+            return Err(ProofError::InvalidProof);
+        }
+
+        let transactions = self.transactions.entry(proof.block_hash.clone()).or_default();
+        if !transactions.iter().any(|t| t.hash == transaction.hash) {
+            transactions.push(transaction);
+        }
+        Ok(())
+    }
+
+    // Calculates the balance of a watched address from the verified transactions
+    // in the active chain
+    //
+    // Sums the outputs sent to the address that no verified transaction spends.
+    // Spending transactions are sent from the address so they are watched too.
+    pub fn calculate_balance(&self, address: &'a str) -> i64 {
+        let spent: HashSet<&OutPoint> = self.active_transactions()
+            .filter(|signed_transaction| !signed_transaction.is_reward())
+            .flat_map(|signed_transaction| signed_transaction.transaction.inputs.iter())
+            .collect();
+
+        let mut balance = 0;
+        for signed_transaction in self.active_transactions() {
+            for (index, output) in signed_transaction.transaction.outputs.iter().enumerate() {
+                if output.address == address && !spent.contains(&signed_transaction.outpoint(index as u64)) {
+                    balance += output.amount;
+                }
+            }
+        }
+        balance
+    }
+
+    // Returns the number of blocks confirming a verified transaction, counting
+    // the block that includes it, or None if it isn't in the active chain
+    pub fn confirmations(&self, transaction_hash: &str) -> Option<u64> {
+        for (index, hash) in self.active_chain.iter().enumerate() {
+            let found = self.transactions.get(hash)
+                .map(|transactions| transactions.iter().any(|t| t.hash == transaction_hash))
+                .unwrap_or(false);
+            if found {
+                return Some(self.height() - index as u64 + 1);
+            }
+        }
+        None
+    }
+
+    // Returns the header at the tip of the active chain
+    pub fn best_header(&self) -> &BlockHeader {
+        &self.headers[self.best_hash()].header
+    }
+
+    // Returns the index of the tip of the active chain
+    pub fn height(&self) -> u64 {
+        self.headers[self.best_hash()].index
+    }
+
+    fn best_hash(&self) -> &String {
+        self.active_chain.last().expect("Unknown latest header")
+    }
+
+    fn active_transactions(&self) -> impl Iterator<Item = &SignedTransaction> {
+        self.active_chain.iter()
+            .filter_map(move |hash| self.transactions.get(hash))
+            .flat_map(|transactions| transactions.iter())
+    }
+
+    // Calculates the target bits required for the header built on previous, see
+    // Blockchain::next_bits
+    fn next_bits(&self, previous: &HeaderEntry) -> u32 {
+        let index = previous.index + 1;
+        if !Blockchain::is_retarget_index(&self.params, index) {
+            return previous.header.bits;
+        }
+
+        let mut first = previous;
+        while first.index > index - self.params.retarget_interval {
+            first = &self.headers[&first.header.previous_hash];
+        }
+        Blockchain::retarget(&self.params, &previous.header, &first.header)
+    }
+
+    // Returns the median timestamp of a header and the headers before it, see
+    // Blockchain::median_time_past
+    fn median_time_past(&self, entry: &HeaderEntry) -> i64 {
+        let timestamps: Vec<i64> = std::iter::successors(Some(entry), |entry| self.headers.get(&entry.header.previous_hash))
+            .take(self.params.median_time_span)
+            .map(|entry| entry.header.timestamp)
+            .collect();
+        blockchain::median(timestamps)
+    }
+
+    // Switches the active chain to end at new_tip
+    fn reorganize(&mut self, new_tip: String) {
+        let mut branch = Vec::new();
+        let mut cursor = new_tip;
+        loop {
+            let entry = &self.headers[&cursor];
+            if self.active_chain.get(entry.index as usize) == Some(&cursor) {
+                break;
+            }
+            let previous_hash = entry.header.previous_hash.clone();
+            branch.push(cursor);
+            cursor = previous_hash;
+        }
+
+        let fork_index = self.headers[&cursor].index as usize;
+        self.active_chain.truncate(fork_index + 1);
+        self.active_chain.extend(branch.into_iter().rev());
+    }
+}
@@ -1,21 +1,17 @@
 extern crate badcoin;
 use badcoin::wallet::Wallet;
 use badcoin::blockchain::Blockchain;
-use badcoin::blockchain::SignedTransaction;
-use badcoin::blockchain::Transaction;
+use badcoin::chain_params::ChainParams;
+use std::error::Error;
 
 fn main() {
     let wallet = Wallet::new();
-    let public_key = wallet.keypair.export_public_key();
-    wallet.send("asdf", 10);
 
-    let transaction = Transaction::create("nobody", &public_key, 20);
-    let signed_transaction = SignedTransaction::create(transaction, &wallet.keypair);
-
-    let mut bc = Blockchain::new(&wallet.keypair);
-    bc.add_pending_transaction(signed_transaction);
-
-    let result = bc.mine_block("sadaf");
+    let mut bc = Blockchain::new(&wallet.keypair, ChainParams::mainnet())
+        .expect("Mainnet params are valid");
+    let result: Result<(), Box<dyn Error>> = wallet.send(&mut bc, "nobody", 20, 1)
+        .map_err(Box::from)
+        .and_then(|_| bc.mine_block("sadaf").map_err(Box::from));
 
     match result {
         Ok(_) => println!("PROFIT"),
@@ -0,0 +1,160 @@
+use crate::block_header::BlockHeader;
+use sha2::{Sha256, Digest};
+
+// Hashes two child nodes into their parent node
+pub fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(left);
+    hasher.input(right);
+    let result = hasher.result();
+
+    let mut hash: [u8; 32] = Default::default();
+    hash.copy_from_slice(result.as_slice());
+    hash
+}
+
+// Calculates the Merkle root for a list of leaf hashes
+//
+// Each level hashes adjacent pairs, pairing the last node with itself when a
+// level has an odd number of nodes. An empty list has a root of all zeros.
+pub fn merkle_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0u8; 32];
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level.chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+    }
+    level[0]
+}
+
+// Builds the branch of sibling hashes needed to recompute the Merkle root from
+// the leaf at index, ordered from the leaf level up
+pub fn merkle_branch(leaves: &[[u8; 32]], index: usize) -> Vec<[u8; 32]> {
+    let mut branch = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut position = index;
+    while level.len() > 1 {
+        let sibling = if position.is_multiple_of(2) {
+            *level.get(position + 1).unwrap_or(&level[position])
+        } else {
+            level[position - 1]
+        };
+        branch.push(sibling);
+
+        level = level.chunks(2)
+            .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+            .collect();
+        position /= 2;
+    }
+    branch
+}
+
+// Proof that a transaction is included in a block
+//
+// Only the block header is needed to check the proof, so it can be verified by
+// clients that don't store full blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    pub transaction_hash: String,
+    pub block_hash: String,
+    // Position of the transaction in the block
+    pub index: usize,
+    pub branch: Vec<[u8; 32]>
+}
+
+impl MerkleProof {
+    // Recomputes the Merkle root from the transaction hash and branch
+    pub fn root(&self) -> Option<[u8; 32]> {
+        let mut hash = [0u8; 32];
+        hex::decode_to_slice(&self.transaction_hash, &mut hash).ok()?;
+
+        let mut position = self.index;
+        for sibling in self.branch.iter() {
+            hash = if position.is_multiple_of(2) {
+                hash_pair(&hash, sibling)
+            } else {
+                hash_pair(sibling, &hash)
+            };
+            position /= 2;
+        }
+
+        // Any bits left over mean the index doesn't fit the branch
+        if position != 0 {
+            return None;
+        }
+        Some(hash)
+    }
+
+    // Checks the proof against the Merkle root committed to by a block header
+    pub fn verify(&self, header: &BlockHeader) -> bool {
+        if header.as_hash() != self.block_hash {
+            return false;
+        }
+
+        match self.root() {
+            Some(root) => hex::encode(root) == header.merkle_root,
+            None => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: u8) -> Vec<[u8; 32]> {
+        (0..count).map(|i| hash_pair(&[i; 32], &[i; 32])).collect()
+    }
+
+    fn proof(leaves: &[[u8; 32]], index: usize, header: &BlockHeader) -> MerkleProof {
+        MerkleProof {
+            transaction_hash: hex::encode(leaves[index]),
+            block_hash: header.as_hash(),
+            index,
+            branch: merkle_branch(leaves, index)
+        }
+    }
+
+    #[test]
+    fn odd_levels_pair_the_last_node_with_itself() {
+        let leaves = leaves(3);
+        let expected = hash_pair(
+            &hash_pair(&leaves[0], &leaves[1]),
+            &hash_pair(&leaves[2], &leaves[2])
+        );
+        assert_eq!(merkle_root(&leaves), expected);
+        assert_eq!(merkle_branch(&leaves, 2), vec![leaves[2], hash_pair(&leaves[0], &leaves[1])]);
+    }
+
+    #[test]
+    fn proves_every_leaf() {
+        for count in [1, 2, 3, 5, 6, 7, 9] {
+            let leaves = leaves(count);
+            let root = hex::encode(merkle_root(&leaves));
+            let header = BlockHeader::new("", &root, 0, 0x2100_ffff, 0);
+            for index in 0..leaves.len() {
+                assert!(proof(&leaves, index, &header).verify(&header), "leaf {} of {}", index, count);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_index_or_header() {
+        let leaves = leaves(5);
+        let root = hex::encode(merkle_root(&leaves));
+        let header = BlockHeader::new("", &root, 0, 0x2100_ffff, 0);
+
+        let mut moved = proof(&leaves, 4, &header);
+        moved.index = 3;
+        assert!(!moved.verify(&header));
+        moved.index = 1 << moved.branch.len();
+        assert_eq!(moved.root(), None);
+
+        let other = BlockHeader::new("", &root, 1, 0x2100_ffff, 0);
+        assert!(!proof(&leaves, 4, &header).verify(&other));
+    }
+}
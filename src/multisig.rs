@@ -0,0 +1,107 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use sha2::{Sha256, Digest};
+use std::collections::HashSet;
+
+use crate::chain_params::ChainParams;
+use crate::keypair;
+
+// An M of N multisignature policy
+//
+// Coins sent to the policy's address can only be spent by a transaction signed
+// by at least threshold of the public keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultisigPolicy {
+    pub threshold: usize,
+    pub public_keys: Vec<String>
+}
+
+// The policy behind a multisig address and the signatures that satisfy it
+//
+// Signatures must be given in the same order as the keys they belong to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Multisig {
+    pub policy: MultisigPolicy,
+    pub signatures: Vec<String>
+}
+
+impl MultisigPolicy {
+    // Starts every multisig address, public key addresses are plain hex
+    pub const ADDRESS_PREFIX: &'static str = "multisig:";
+
+    pub fn new(threshold: usize, public_keys: Vec<String>) -> MultisigPolicy {
+        MultisigPolicy {
+            threshold,
+            public_keys
+        }
+    }
+
+    // Checks whether an address belongs to a multisig policy rather than a
+    // single public key
+    pub fn is_multisig_address(address: &str) -> bool {
+        address.starts_with(MultisigPolicy::ADDRESS_PREFIX)
+    }
+
+    // Derives the address from a hash of the threshold and keys, so the order of
+    // the keys matters
+    pub fn address(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.input(self.as_bytes());
+        format!("{}{}", MultisigPolicy::ADDRESS_PREFIX, hex::encode(hasher.result()))
+    }
+
+    // Requires between 1 and max_multisig_keys distinct keys and a threshold no
+    // larger than the number of keys
+    pub fn is_valid(&self, params: &ChainParams) -> bool {
+        let unique: HashSet<&String> = self.public_keys.iter().collect();
+        self.threshold > 0
+            && self.threshold <= self.public_keys.len()
+            && self.public_keys.len() <= params.max_multisig_keys
+            && unique.len() == self.public_keys.len()
+    }
+
+    // Checks exactly threshold signatures over message, each from a different
+    // key and in key order
+    pub fn verify(&self, signatures: &[String], message: &[u8], params: &ChainParams) -> bool {
+        if !self.is_valid(params) || signatures.len() != self.threshold {
+            return false;
+        }
+
+        let mut keys = self.public_keys.iter();
+        signatures.iter().all(|signature| {
+            keys.any(|public_key| keypair::verify_signature(public_key, signature, message))
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.write_u32::<LittleEndian>(self.threshold as u32).expect("Unable to serialize threshold");
+        bytes.write_u32::<LittleEndian>(self.public_keys.len() as u32).expect("Unable to serialize public keys");
+        for public_key in self.public_keys.iter() {
+            bytes.extend_from_slice(public_key.as_bytes());
+        }
+        bytes
+    }
+}
+
+impl Multisig {
+    pub fn new(policy: MultisigPolicy, signatures: Vec<String>) -> Multisig {
+        Multisig {
+            policy,
+            signatures
+        }
+    }
+
+    // Checks the policy belongs to address and the signatures satisfy it
+    pub fn verify(&self, address: &str, message: &[u8], params: &ChainParams) -> bool {
+        self.policy.address() == address && self.policy.verify(&self.signatures, message, params)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.policy.as_bytes();
+        bytes.write_u32::<LittleEndian>(self.signatures.len() as u32).expect("Unable to serialize signatures");
+        for signature in self.signatures.iter() {
+            bytes.extend_from_slice(signature.as_bytes());
+        }
+        bytes
+    }
+}
@@ -0,0 +1,57 @@
+use chrono::Utc;
+
+// Estimates network time from the clock offsets reported by peers
+//
+// Each peer counts once, so a single peer can't outvote the others by reporting
+// repeatedly. The median offset is only trusted once enough peers have reported
+// and is ignored if it is so large that the local clock is more likely to be
+// right.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkTime {
+    // Latest offset reported by each peer, oldest peer first
+    offsets: Vec<(String, i64)>
+}
+
+impl NetworkTime {
+    pub const MIN_SAMPLES: usize = 5;
+    pub const MAX_SAMPLES: usize = 200;
+    // Largest offset in seconds that will be applied to the local clock
+    pub const MAX_OFFSET: i64 = 70 * 60;
+
+    pub fn new() -> NetworkTime {
+        NetworkTime {
+            offsets: Vec::new()
+        }
+    }
+
+    // Records the current time reported by a peer, replacing its earlier offset
+    // and keeping the offsets of the MAX_SAMPLES peers heard from most recently
+    pub fn add_sample(&mut self, peer: &str, peer_time: i64) {
+        if let Some(position) = self.offsets.iter().position(|(known, _)| known == peer) {
+            self.offsets.remove(position);
+        } else if self.offsets.len() >= NetworkTime::MAX_SAMPLES {
+            self.offsets.remove(0);
+        }
+        self.offsets.push((peer.to_string(), peer_time - Utc::now().timestamp()));
+    }
+
+    // The median peer offset, or 0 if it can't be trusted
+    pub fn offset(&self) -> i64 {
+        if self.offsets.len() < NetworkTime::MIN_SAMPLES {
+            return 0;
+        }
+
+        let mut offsets: Vec<i64> = self.offsets.iter().map(|(_, offset)| *offset).collect();
+        offsets.sort_unstable();
+        let median = offsets[offsets.len() / 2];
+        if median.abs() > NetworkTime::MAX_OFFSET {
+            return 0;
+        }
+        median
+    }
+
+    // The local clock adjusted by the peer offset
+    pub fn now(&self) -> i64 {
+        Utc::now().timestamp() + self.offset()
+    }
+}
@@ -0,0 +1,495 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use sha2::{Sha256, Digest};
+
+use crate::chain_params::ChainParams;
+use crate::error::ScriptError;
+use crate::keypair;
+
+// A single script instruction
+//
+// Stack items are byte strings. Numbers are little endian two's complement of
+// at most 8 bytes, with zero as the empty string, and any item with a non zero
+// byte counts as true.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    // Pushes data onto the stack
+    Push(Vec<u8>),
+    // Pushes a number onto the stack
+    Number(i64),
+    Dup,
+    Drop,
+    Swap,
+    Over,
+    // Runs the following ops up to Else or EndIf if the top item is true
+    If,
+    NotIf,
+    Else,
+    EndIf,
+    // Fails unless the top item is true, which is removed
+    Verify,
+    // Fails immediately, marking an output that can never be spent
+    Return,
+    Equal,
+    EqualVerify,
+    Sha256,
+    // Two rounds of Sha256
+    Hash256,
+    // Pops a public key and then a signature, both hex encoded, and pushes
+    // whether the signature is valid for the spending transaction
+    CheckSig,
+    CheckSigVerify,
+    Add,
+    Sub,
+    NumEqual,
+    NumEqualVerify,
+    LessThan,
+    GreaterThan,
+    // Pops a block index and fails unless the transaction is being included
+    // in that block or later
+    CheckHeightVerify,
+    // Pops a timestamp and fails unless the median time past of the chain the
+    // transaction is included in has reached it
+    CheckTimeVerify
+}
+
+// A list of ops run in order, there are no loops or jumps backwards so every
+// script finishes after at most max_script_ops steps, see ChainParams
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    pub ops: Vec<Op>
+}
+
+// The scripts needed to spend coins sent to a script address
+//
+// The locking script must hash to the address. The unlocking script may only
+// push data, which the locking script then consumes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptWitness {
+    pub locking: Script,
+    pub unlocking: Script
+}
+
+// What a script can see about the transaction spending it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptContext {
+    // Signed message, see Transaction::as_hash
+    pub message: [u8; 32],
+    // Index of the block the transaction is included in
+    pub height: u64,
+    // Median time past of the chain the block builds on
+    pub time: i64
+}
+
+impl Op {
+    // A helper function to push a string, such as a hex public key or signature
+    pub fn push_str(data: &str) -> Op {
+        Op::Push(data.as_bytes().to_vec())
+    }
+
+    fn is_push(&self) -> bool {
+        matches!(self, Op::Push(_) | Op::Number(_))
+    }
+
+    fn code(&self) -> u8 {
+        match self {
+            Op::Push(_) => 0x01,
+            Op::Number(_) => 0x02,
+            Op::Dup => 0x10,
+            Op::Drop => 0x11,
+            Op::Swap => 0x12,
+            Op::Over => 0x13,
+            Op::If => 0x20,
+            Op::NotIf => 0x21,
+            Op::Else => 0x22,
+            Op::EndIf => 0x23,
+            Op::Verify => 0x24,
+            Op::Return => 0x25,
+            Op::Equal => 0x30,
+            Op::EqualVerify => 0x31,
+            Op::Sha256 => 0x40,
+            Op::Hash256 => 0x41,
+            Op::CheckSig => 0x50,
+            Op::CheckSigVerify => 0x51,
+            Op::Add => 0x60,
+            Op::Sub => 0x61,
+            Op::NumEqual => 0x62,
+            Op::NumEqualVerify => 0x63,
+            Op::LessThan => 0x64,
+            Op::GreaterThan => 0x65,
+            Op::CheckHeightVerify => 0x70,
+            Op::CheckTimeVerify => 0x71
+        }
+    }
+}
+
+impl Script {
+    // Starts every script address
+    pub const ADDRESS_PREFIX: &'static str = "script:";
+
+    pub fn new(ops: Vec<Op>) -> Script {
+        Script {
+            ops
+        }
+    }
+
+    // Checks whether an address belongs to a script rather than a public key
+    pub fn is_script_address(address: &str) -> bool {
+        address.starts_with(Script::ADDRESS_PREFIX)
+    }
+
+    // Derives the address coins are sent to from a hash of the script
+    pub fn address(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.input(self.as_bytes());
+        format!("{}{}", Script::ADDRESS_PREFIX, hex::encode(hasher.result()))
+    }
+
+    pub fn is_push_only(&self) -> bool {
+        self.ops.iter().all(Op::is_push)
+    }
+
+    // Converts the script to a byte vector, each op is a one byte code followed
+    // by its data
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for op in self.ops.iter() {
+            bytes.push(op.code());
+            match op {
+                Op::Push(data) => {
+                    bytes.write_u32::<LittleEndian>(data.len() as u32).expect("Unable to serialize push");
+                    bytes.extend_from_slice(data);
+                },
+                Op::Number(number) => {
+                    bytes.write_i64::<LittleEndian>(*number).expect("Unable to serialize number");
+                },
+                _ => ()
+            }
+        }
+        bytes
+    }
+}
+
+impl ScriptWitness {
+    pub fn new(locking: Script, unlocking: Script) -> ScriptWitness {
+        ScriptWitness {
+            locking,
+            unlocking
+        }
+    }
+
+    // Checks the locking script belongs to address and the unlocking script
+    // satisfies it
+    pub fn verify(&self, address: &str, context: &ScriptContext, params: &ChainParams) -> Result<(), ScriptError> {
+        if self.locking.address() != address {
+            return Err(ScriptError::WrongScript);
+        }
+        verify(&self.unlocking, &self.locking, context, params)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let locking = self.locking.as_bytes();
+        let mut bytes = Vec::new();
+        bytes.write_u32::<LittleEndian>(locking.len() as u32).expect("Unable to serialize locking script");
+        bytes.extend_from_slice(&locking);
+        bytes.extend_from_slice(&self.unlocking.as_bytes());
+        bytes
+    }
+}
+
+// Runs the unlocking script and then the locking script on the same stack
+//
+// Succeeds if neither script fails and the top item left on the stack is true.
+pub fn verify(unlocking: &Script, locking: &Script, context: &ScriptContext, params: &ChainParams) -> Result<(), ScriptError> {
+    if !unlocking.is_push_only() {
+        return Err(ScriptError::NotPushOnly);
+    }
+
+    let mut stack = Vec::new();
+    execute(unlocking, &mut stack, context, params)?;
+    execute(locking, &mut stack, context, params)?;
+
+    match stack.last() {
+        Some(item) if is_true(item) => Ok(()),
+        _ => Err(ScriptError::FalseResult)
+    }
+}
+
+// Runs a script on a stack, within the script limits of params
+pub fn execute(script: &Script, stack: &mut Vec<Vec<u8>>, context: &ScriptContext, params: &ChainParams) -> Result<(), ScriptError> {
+    if script.ops.len() > params.max_script_ops {
+        return Err(ScriptError::TooManyOps);
+    }
+
+    // Whether each enclosing If branch is being run
+    let mut branches: Vec<bool> = Vec::new();
+    for op in script.ops.iter() {
+        let is_running = branches.iter().all(|branch| *branch);
+        match op {
+            Op::If | Op::NotIf => {
+                let mut condition = false;
+                if is_running {
+                    condition = is_true(&pop(stack)?);
+                    if *op == Op::NotIf {
+                        condition = !condition;
+                    }
+                }
+                branches.push(condition);
+                continue;
+            },
+            Op::Else => {
+                let branch = branches.last_mut().ok_or(ScriptError::UnbalancedConditional)?;
+                *branch = !*branch;
+                continue;
+            },
+            Op::EndIf => {
+                branches.pop().ok_or(ScriptError::UnbalancedConditional)?;
+                continue;
+            },
+            _ if !is_running => continue,
+            _ => ()
+        }
+
+        match op {
+            Op::Push(data) => {
+                if data.len() > params.max_push_size {
+                    return Err(ScriptError::PushTooLarge);
+                }
+                stack.push(data.clone());
+            },
+            Op::Number(number) => stack.push(encode_number(*number)),
+            Op::Dup => {
+                let item = stack.last().ok_or(ScriptError::StackUnderflow)?.clone();
+                stack.push(item);
+            },
+            Op::Drop => {
+                pop(stack)?;
+            },
+            Op::Swap => {
+                let top = pop(stack)?;
+                let below = pop(stack)?;
+                stack.push(top);
+                stack.push(below);
+            },
+            Op::Over => {
+                if stack.len() < 2 {
+                    return Err(ScriptError::StackUnderflow);
+                }
+                let item = stack[stack.len() - 2].clone();
+                stack.push(item);
+            },
+            Op::Verify => verify_top(stack)?,
+            Op::Return => return Err(ScriptError::Return),
+            Op::Equal | Op::EqualVerify => {
+                let a = pop(stack)?;
+                let b = pop(stack)?;
+                stack.push(encode_bool(a == b));
+                if *op == Op::EqualVerify {
+                    verify_top(stack)?;
+                }
+            },
+            Op::Sha256 => {
+                let item = pop(stack)?;
+                stack.push(sha256(&item).to_vec());
+            },
+            Op::Hash256 => {
+                let item = pop(stack)?;
+                stack.push(sha256(&sha256(&item)).to_vec());
+            },
+            Op::CheckSig | Op::CheckSigVerify => {
+                let public_key = pop(stack)?;
+                let signature = pop(stack)?;
+                let is_valid = match (std::str::from_utf8(&public_key), std::str::from_utf8(&signature)) {
+                    (Ok(public_key), Ok(signature)) => keypair::verify_signature(public_key, signature, &context.message),
+                    _ => false
+                };
+                stack.push(encode_bool(is_valid));
+                if *op == Op::CheckSigVerify {
+                    verify_top(stack)?;
+                }
+            },
+            Op::Add | Op::Sub | Op::NumEqual | Op::NumEqualVerify | Op::LessThan | Op::GreaterThan => {
+                let b = decode_number(&pop(stack)?)?;
+                let a = decode_number(&pop(stack)?)?;
+                let result = match op {
+                    Op::Add => encode_number(a.checked_add(b).ok_or(ScriptError::InvalidNumber)?),
+                    Op::Sub => encode_number(a.checked_sub(b).ok_or(ScriptError::InvalidNumber)?),
+                    Op::LessThan => encode_bool(a < b),
+                    Op::GreaterThan => encode_bool(a > b),
+                    _ => encode_bool(a == b)
+                };
+                stack.push(result);
+                if *op == Op::NumEqualVerify {
+                    verify_top(stack)?;
+                }
+            },
+            Op::CheckHeightVerify => {
+                let height = decode_number(&pop(stack)?)?;
+                if height < 0 || context.height < height as u64 {
+                    return Err(ScriptError::Locked);
+                }
+            },
+            Op::CheckTimeVerify => {
+                let time = decode_number(&pop(stack)?)?;
+                if context.time < time {
+                    return Err(ScriptError::Locked);
+                }
+            },
+            Op::If | Op::NotIf | Op::Else | Op::EndIf => ()
+        }
+
+        if stack.len() > params.max_stack_size {
+            return Err(ScriptError::StackOverflow);
+        }
+    }
+
+    if !branches.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+    Ok(())
+}
+
+pub fn encode_number(number: i64) -> Vec<u8> {
+    if number == 0 {
+        return Vec::new();
+    }
+
+    // Drop high bytes that only repeat the sign
+    let mut bytes = number.to_le_bytes().to_vec();
+    while bytes.len() > 1 {
+        let last = bytes[bytes.len() - 1];
+        let sign = bytes[bytes.len() - 2] & 0x80;
+        if (last == 0x00 && sign == 0) || (last == 0xff && sign != 0) {
+            bytes.pop();
+        } else {
+            break;
+        }
+    }
+    bytes
+}
+
+pub fn decode_number(bytes: &[u8]) -> Result<i64, ScriptError> {
+    if bytes.len() > 8 {
+        return Err(ScriptError::InvalidNumber);
+    }
+    if bytes.is_empty() {
+        return Ok(0);
+    }
+
+    let fill = if bytes[bytes.len() - 1] & 0x80 != 0 { 0xff } else { 0x00 };
+    let mut number = [fill; 8];
+    number[..bytes.len()].copy_from_slice(bytes);
+    Ok(i64::from_le_bytes(number))
+}
+
+fn encode_bool(value: bool) -> Vec<u8> {
+    if value {
+        vec![1]
+    } else {
+        Vec::new()
+    }
+}
+
+fn is_true(item: &[u8]) -> bool {
+    item.iter().any(|byte| *byte != 0)
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> Result<Vec<u8>, ScriptError> {
+    stack.pop().ok_or(ScriptError::StackUnderflow)
+}
+
+fn verify_top(stack: &mut Vec<Vec<u8>>) -> Result<(), ScriptError> {
+    if is_true(&pop(stack)?) {
+        Ok(())
+    } else {
+        Err(ScriptError::VerifyFailed)
+    }
+}
+
+fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    let result = hasher.result();
+
+    let mut hash: [u8; 32] = Default::default();
+    hash.copy_from_slice(result.as_slice());
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(height: u64, time: i64) -> ScriptContext {
+        ScriptContext {
+            message: [0u8; 32],
+            height,
+            time
+        }
+    }
+
+    fn run(ops: Vec<Op>, params: &ChainParams) -> Result<Vec<Vec<u8>>, ScriptError> {
+        let mut stack = Vec::new();
+        execute(&Script::new(ops), &mut stack, &context(10, 1000), params)?;
+        Ok(stack)
+    }
+
+    #[test]
+    fn runs_conditionals() {
+        let params = ChainParams::regtest();
+        let ops = vec![Op::Number(0), Op::If, Op::Number(1), Op::Else, Op::Number(2), Op::EndIf];
+        assert_eq!(run(ops, &params), Ok(vec![encode_number(2)]));
+
+        // Ops in a branch that isn't run can't fail
+        let ops = vec![Op::Number(1), Op::NotIf, Op::Return, Op::Drop, Op::EndIf, Op::Number(3)];
+        assert_eq!(run(ops, &params), Ok(vec![encode_number(3)]));
+    }
+
+    #[test]
+    fn rejects_unbalanced_conditionals() {
+        let params = ChainParams::regtest();
+        for ops in [
+            vec![Op::Number(1), Op::If],
+            vec![Op::Number(0), Op::If, Op::Number(1), Op::If, Op::EndIf],
+            vec![Op::Else],
+            vec![Op::Number(1), Op::EndIf],
+            vec![Op::Number(1), Op::If, Op::EndIf, Op::EndIf]
+        ] {
+            assert_eq!(run(ops, &params), Err(ScriptError::UnbalancedConditional));
+        }
+    }
+
+    #[test]
+    fn limits_the_stack() {
+        let params = ChainParams::regtest();
+        assert_eq!(run(vec![Op::Number(1); params.max_stack_size], &params).map(|stack| stack.len()), Ok(params.max_stack_size));
+        assert_eq!(run(vec![Op::Number(1); params.max_stack_size + 1], &params), Err(ScriptError::StackOverflow));
+
+        let mut ops = vec![Op::Number(1); params.max_stack_size];
+        ops.push(Op::Dup);
+        assert_eq!(run(ops, &params), Err(ScriptError::StackOverflow));
+
+        assert_eq!(run(vec![Op::Number(1); params.max_script_ops + 1], &params), Err(ScriptError::TooManyOps));
+        assert_eq!(run(vec![Op::Push(vec![0; params.max_push_size + 1])], &params), Err(ScriptError::PushTooLarge));
+        assert_eq!(run(vec![Op::Drop], &params), Err(ScriptError::StackUnderflow));
+    }
+
+    #[test]
+    fn checks_time_locks() {
+        let params = ChainParams::regtest();
+        assert_eq!(run(vec![Op::Number(10), Op::CheckHeightVerify], &params), Ok(Vec::new()));
+        assert_eq!(run(vec![Op::Number(11), Op::CheckHeightVerify], &params), Err(ScriptError::Locked));
+        assert_eq!(run(vec![Op::Number(-1), Op::CheckHeightVerify], &params), Err(ScriptError::Locked));
+        assert_eq!(run(vec![Op::Number(1000), Op::CheckTimeVerify], &params), Ok(Vec::new()));
+        assert_eq!(run(vec![Op::Number(1001), Op::CheckTimeVerify], &params), Err(ScriptError::Locked));
+    }
+
+    #[test]
+    fn verify_needs_a_true_result() {
+        let params = ChainParams::regtest();
+        let context = context(10, 1000);
+        let locking = Script::new(vec![Op::Sha256, Op::Push(sha256(b"secret").to_vec()), Op::Equal]);
+        assert_eq!(verify(&Script::new(vec![Op::push_str("secret")]), &locking, &context, &params), Ok(()));
+        assert_eq!(verify(&Script::new(vec![Op::push_str("guess")]), &locking, &context, &params), Err(ScriptError::FalseResult));
+        assert_eq!(verify(&Script::new(vec![Op::push_str("secret"), Op::Dup]), &locking, &context, &params), Err(ScriptError::NotPushOnly));
+    }
+}
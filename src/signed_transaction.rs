@@ -1,86 +1,211 @@
-use crate::transaction::Transaction;
-use crate::keypair;
+use crate::transaction::{OutPoint, Output, Transaction};
+use crate::keypair::{self, SignatureScheme};
+use crate::multisig::{Multisig, MultisigPolicy};
+use crate::script::{Script, ScriptContext, ScriptWitness};
+use crate::chain_params::ChainParams;
+use crate::error::ScriptError;
 use sha2::{Sha256, Digest};
+use std::cmp::Ordering;
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SignedTransaction {
     pub transaction: Transaction,
+    // Signature by the sending public key, empty when sent from a multisig or
+    // script address
     pub signature: String,
+    // Signatures for a transaction sent from a multisig address
+    pub multisig: Option<Multisig>,
+    // Scripts for a transaction sent from a script address
+    pub script: Option<ScriptWitness>,
     pub hash: String
 }
 
 impl<'a> SignedTransaction {
     // Recreates a new SignedTransaction if all fields are known
-    pub fn new(to: &'a str, from: &'a str, timestamp:i64, amount: i64, signature: &'a str, hash: &'a str) -> SignedTransaction {
-        let transaction = Transaction::new(to, from, timestamp, amount);
+    pub fn new(transaction: Transaction, signature: &'a str, multisig: Option<Multisig>, script: Option<ScriptWitness>, hash: &'a str) -> SignedTransaction {
         SignedTransaction {
-            transaction: transaction,
+            transaction,
             signature: signature.to_string(),
+            multisig,
+            script,
             hash: hash.to_string()
         }
     }
 
     // Signs and hashes a transaction
     pub fn create(transaction: Transaction, keypair: &keypair::Keypair) -> SignedTransaction {
-        // TODO: there has to be a better way to convert to slice
-        let signature = keypair.sign(&transaction.as_hash()[0..32]);
-        let mut hasher = Sha256::new();
+        SignedTransaction::create_with_scheme(transaction, keypair, SignatureScheme::Ecdsa)
+    }
 
-        hasher.input(transaction.as_bytes());
-        hasher.input(signature.as_bytes());
-        let result = hasher.result();
+    // Signs and hashes a transaction with a given signature scheme
+    pub fn create_with_scheme(transaction: Transaction, keypair: &keypair::Keypair, scheme: SignatureScheme) -> SignedTransaction {
+        // TODO: there has to be a better way to convert to slice
+        let signature = keypair.sign_with(&transaction.as_hash()[0..32], scheme);
+        SignedTransaction::create_with(transaction, signature, None, None)
+    }
 
-        let mut hash: [u8; 32] = Default::default();
-        hash.copy_from_slice(result.as_slice());
+    // Hashes a transaction sent from the address of a multisig policy
+    //
+    // Each signer signs Transaction::as_hash separately, see Keypair::sign, and
+    // the signatures are listed in the order of the policy's keys.
+    pub fn create_multisig(transaction: Transaction, policy: MultisigPolicy, signatures: Vec<String>) -> SignedTransaction {
+        let multisig = Multisig::new(policy, signatures);
+        SignedTransaction::create_with(transaction, String::new(), Some(multisig), None)
+    }
 
-        SignedTransaction {
-            transaction: transaction,
-            signature: signature,
-            hash: hex::encode(hash)
-        }
+    // Hashes a transaction sent from the address of a locking script
+    //
+    // Signatures checked by the locking script are made over
+    // Transaction::as_hash, the same as for a public key address.
+    pub fn create_script(transaction: Transaction, locking: Script, unlocking: Script) -> SignedTransaction {
+        let script = ScriptWitness::new(locking, unlocking);
+        SignedTransaction::create_with(transaction, String::new(), None, Some(script))
     }
 
     // A helper function to create a reward transaction for miners
-    pub fn create_reward(reward_address: &str) -> SignedTransaction {
-        let transaction = Transaction::create(reward_address, "0", 10);
-        let signature = "0";
+    pub fn create_reward(reward_address: &str, amount: i64, block_index: u64) -> SignedTransaction {
+        let output = Output::new(reward_address, amount);
+        let transaction = Transaction::create("0", 0, vec![OutPoint::reward(block_index)], vec![output], 0);
+        SignedTransaction::create_with(transaction, "0".to_string(), None, None)
+    }
 
-        let mut hasher = Sha256::new();
-        hasher.input(transaction.as_bytes());
-        hasher.input(signature.as_bytes());
-        let result = hasher.result();
+    // Creates the transaction minting coins in the genesis block
+    //
+    // Like a reward it has no signature, but the timestamp is given so the same
+    // allocation always hashes the same.
+    pub fn create_allocation(outputs: Vec<Output>, timestamp: i64) -> SignedTransaction {
+        let transaction = Transaction::new("0", 0, vec![OutPoint::reward(0)], outputs, timestamp, 0);
+        SignedTransaction::create_with(transaction, "0".to_string(), None, None)
+    }
 
-        let mut hash: [u8; 32] = Default::default();
-        hash.copy_from_slice(result.as_slice());
+    fn create_with(transaction: Transaction, signature: String, multisig: Option<Multisig>, script: Option<ScriptWitness>) -> SignedTransaction {
+        let mut signed_transaction = SignedTransaction {
+            transaction,
+            signature,
+            multisig,
+            script,
+            hash: String::new()
+        };
+        signed_transaction.hash = signed_transaction.calculate_hash();
+        signed_transaction
+    }
 
-        SignedTransaction {
-            transaction: transaction,
-            signature: signature.to_string(),
-            hash: hex::encode(hash)
-        }
+    // Reward transactions mint new coins so they have no sending address
+    pub fn is_reward(&self) -> bool {
+        self.transaction.from == "0"
     }
 
     // Verifies the signature and hash for a transaction
-    pub fn is_valid(&self) -> bool {
-        let mut hasher = Sha256::new();
+    //
+    // Transactions from a multisig address must carry the policy behind the
+    // address and enough signatures to satisfy it, see MultisigPolicy::verify.
+    //
+    // Transactions from a script address must carry a locking script that hashes
+    // to the address. The scripts themselves depend on the block the transaction
+    // is included in, so they are run separately by verify_script.
+    pub fn is_valid(&self, params: &ChainParams) -> bool {
+        let from = &self.transaction.from;
+        let message = self.transaction.as_hash();
+        let is_verified = match (&self.multisig, &self.script) {
+            (Some(multisig), None) => self.signature.is_empty() && multisig.verify(from, &message, params),
+            (None, Some(script)) => self.signature.is_empty() && script.locking.address() == *from,
+            (None, None) => !MultisigPolicy::is_multisig_address(from)
+                && !Script::is_script_address(from)
+                && keypair::verify_signature(from, &self.signature, &message),
+            (Some(_), Some(_)) => false
+        };
+
+        self.has_valid_hash() && is_verified
+    }
+
+    // Runs the scripts of a transaction sent from a script address, see
+    // script::verify
+    //
+    // height is the index of the block including the transaction and time the
+    // median time past of the chain it builds on. Transactions from other
+    // addresses have no scripts and always pass.
+    pub fn verify_script(&self, height: u64, time: i64, params: &ChainParams) -> Result<(), ScriptError> {
+        let from = &self.transaction.from;
+        match &self.script {
+            Some(script) => {
+                let context = ScriptContext {
+                    message: self.transaction.as_hash(),
+                    height,
+                    time
+                };
+                script.verify(from, &context, params)
+            },
+            None if Script::is_script_address(from) => Err(ScriptError::MissingScript),
+            None => Ok(())
+        }
+    }
+
+    // Verifies a reward transaction, which has no signature or fee, belongs to
+    // the block at block_index and mints the expected amount
+    pub fn is_valid_reward(&self, block_index: u64, amount: i64) -> bool {
+        self.is_reward()
+            && self.signature == "0"
+            && self.multisig.is_none()
+            && self.script.is_none()
+            && self.transaction.fee == 0
+            && self.transaction.inputs == [OutPoint::reward(block_index)]
+            && self.transaction.outputs.iter().all(|output| output.amount >= 0 && !output.is_data())
+            && self.transaction.output_total() == Some(amount)
+            && self.has_valid_hash()
+    }
 
+    // Returns a reference to one of this transaction's outputs
+    pub fn outpoint(&self, index: u64) -> OutPoint {
+        OutPoint::new(&self.hash, index)
+    }
+
+    fn has_valid_hash(&self) -> bool {
+        self.calculate_hash() == self.hash
+    }
+
+    // Hashes the transaction along with its signatures
+    fn calculate_hash(&self) -> String {
+        let mut hasher = Sha256::new();
         hasher.input(self.transaction.as_bytes());
         hasher.input(self.signature.as_bytes());
-        let result = hasher.result();
+        if let Some(multisig) = &self.multisig {
+            hasher.input(multisig.as_bytes());
+        }
+        if let Some(script) = &self.script {
+            hasher.input(script.as_bytes());
+        }
+        hex::encode(hasher.result())
+    }
 
-        let mut hash: [u8; 32] = Default::default();
-        hash.copy_from_slice(result.as_slice());
+    // Decodes the transaction hash, which is used as the transaction's leaf in
+    // the block Merkle tree. A malformed hash decodes to all zeros.
+    pub fn hash_bytes(&self) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        if hex::decode_to_slice(&self.hash, &mut hash).is_err() {
+            return [0u8; 32];
+        }
+        hash
+    }
 
-        let is_verified = keypair::verify_signature(&self.transaction.from, &self.signature, &self.transaction.as_hash());
+    // Size in bytes, used to rank transactions by fee rate
+    pub fn size(&self) -> usize {
+        self.as_bytes().len()
+    }
 
-        hex::encode(hash) == self.hash && is_verified
+    // Compares fee per byte without rounding
+    pub fn cmp_fee_rate(&self, other: &SignedTransaction) -> Ordering {
+        let rate = i128::from(self.transaction.fee) * other.size() as i128;
+        let other_rate = i128::from(other.transaction.fee) * self.size() as i128;
+        rate.cmp(&other_rate)
     }
 
     // Converts all transaction fields to a byte vector
     pub fn as_bytes(&self) -> Vec<u8> {
         let transaction_bytes = self.transaction.as_bytes();
-        [transaction_bytes, self.signature.as_bytes().to_vec(), self.hash.as_bytes().to_vec()].concat()
+        let multisig_bytes = self.multisig.as_ref().map(Multisig::as_bytes).unwrap_or_default();
+        let script_bytes = self.script.as_ref().map(ScriptWitness::as_bytes).unwrap_or_default();
+        [transaction_bytes, self.signature.as_bytes().to_vec(), multisig_bytes, script_bytes, self.hash.as_bytes().to_vec()].concat()
     }
 
     // Hashes a SignedTransaction
@@ -95,7 +220,7 @@ impl<'a> SignedTransaction {
     }
 }
 
-impl<'a> fmt::Display for SignedTransaction {
+impl fmt::Display for SignedTransaction {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_fmt(format_args!("SignedTransaction(transaction: {}, signature: {}, hash: {:?})", self.transaction, self.signature, self.hash))
     }
@@ -0,0 +1,302 @@
+use std::fmt;
+use std::ops::{Div, Shl, Shr};
+
+// A 256 bit unsigned integer used for proof of work targets and chain work
+//
+// Limbs are stored most significant first so the derived ordering matches the
+// numeric ordering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0; 4]);
+    pub const ONE: U256 = U256([0, 0, 0, 1]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(value: u64) -> U256 {
+        U256([0, 0, 0, value])
+    }
+
+    // Reads a big endian byte array, such as a block hash
+    pub fn from_be_bytes(bytes: [u8; 32]) -> U256 {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let mut chunk = [0u8; 8];
+            chunk.copy_from_slice(&bytes[i * 8..i * 8 + 8]);
+            *limb = u64::from_be_bytes(chunk);
+        }
+        U256(limbs)
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            bytes[i * 8..i * 8 + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    // Decodes a target from the compact "bits" format
+    //
+    // The high byte is the length of the target in bytes and the low 3 bytes are
+    // the most significant bytes of the target. Negative targets (sign bit set)
+    // decode to zero.
+    pub fn from_compact(bits: u32) -> U256 {
+        let size = bits >> 24;
+        let mantissa = bits & 0x007f_ffff;
+        if bits & 0x0080_0000 != 0 || mantissa == 0 {
+            return U256::ZERO;
+        }
+
+        if size <= 3 {
+            U256::from_u64(u64::from(mantissa >> (8 * (3 - size))))
+        } else {
+            U256::from_u64(u64::from(mantissa)) << (8 * (size - 3))
+        }
+    }
+
+    // Encodes a target into the compact "bits" format, truncating to the 3 most
+    // significant bytes
+    pub fn to_compact(self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut mantissa = if size <= 3 {
+            (self.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            (self >> (8 * (size - 3))).low_u64() as u32
+        };
+
+        // The mantissa is signed so move up a byte rather than set the sign bit
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        mantissa | (size << 24)
+    }
+
+    // Returns the number of bits needed to represent the value
+    pub fn bits(self) -> u32 {
+        for (i, limb) in self.0.iter().enumerate() {
+            if *limb != 0 {
+                return 64 * (4 - i as u32) - limb.leading_zeros();
+            }
+        }
+        0
+    }
+
+    pub fn low_u64(self) -> u64 {
+        self.0[3]
+    }
+
+    pub fn saturating_add(self, other: U256) -> U256 {
+        let mut result = [0u64; 4];
+        let mut carry = false;
+        for i in (0..4).rev() {
+            let (sum, overflow_a) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, overflow_b) = sum.overflowing_add(carry as u64);
+            result[i] = sum;
+            carry = overflow_a || overflow_b;
+        }
+        if carry {
+            U256::MAX
+        } else {
+            U256(result)
+        }
+    }
+
+    // Subtracts other from self, the caller must ensure other <= self
+    fn wrapping_sub(self, other: U256) -> U256 {
+        let mut result = [0u64; 4];
+        let mut borrow = false;
+        for i in (0..4).rev() {
+            let (difference, borrow_a) = self.0[i].overflowing_sub(other.0[i]);
+            let (difference, borrow_b) = difference.overflowing_sub(borrow as u64);
+            result[i] = difference;
+            borrow = borrow_a || borrow_b;
+        }
+        U256(result)
+    }
+
+    // Computes self * multiplier / divisor without overflowing the intermediate
+    // product, saturating at U256::MAX if the result does not fit
+    pub fn mul_div(self, multiplier: u64, divisor: u64) -> U256 {
+        // Multiply into 5 limbs, least significant first
+        let mut product = [0u64; 5];
+        let mut carry = 0u128;
+        for (limb, value) in product.iter_mut().zip(self.0.iter().rev()) {
+            let value = u128::from(*value) * u128::from(multiplier) + carry;
+            *limb = value as u64;
+            carry = value >> 64;
+        }
+        product[4] = carry as u64;
+
+        let mut remainder = 0u128;
+        for limb in product.iter_mut().rev() {
+            let value = (remainder << 64) | u128::from(*limb);
+            *limb = (value / u128::from(divisor)) as u64;
+            remainder = value % u128::from(divisor);
+        }
+
+        if product[4] != 0 {
+            return U256::MAX;
+        }
+        U256([product[3], product[2], product[1], product[0]])
+    }
+
+    // Returns the expected number of hashes needed to find a hash at or below
+    // this target, 2^256 / (target + 1)
+    pub fn work(self) -> U256 {
+        if self == U256::MAX {
+            return U256::ONE;
+        }
+        // 2^256 doesn't fit so use (2^256 - target - 1) / (target + 1) + 1
+        let inverse = U256(self.0.map(|limb| !limb));
+        (inverse / self.saturating_add(U256::ONE)).saturating_add(U256::ONE)
+    }
+
+    fn bit(self, index: u32) -> bool {
+        self.0[3 - (index / 64) as usize] & (1 << (index % 64)) != 0
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = U256;
+
+    fn shl(self, shift: u32) -> U256 {
+        let mut result = [0u64; 4];
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        for (i, limb) in result.iter_mut().enumerate().take(4usize.saturating_sub(limbs)) {
+            *limb = self.0[i + limbs] << bits;
+            if bits > 0 && i + limbs + 1 < 4 {
+                *limb |= self.0[i + limbs + 1] >> (64 - bits);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = U256;
+
+    fn shr(self, shift: u32) -> U256 {
+        let mut result = [0u64; 4];
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        for i in (0..4).rev() {
+            if i < limbs {
+                break;
+            }
+            result[i] = self.0[i - limbs] >> bits;
+            if bits > 0 && i > limbs {
+                result[i] |= self.0[i - limbs - 1] << (64 - bits);
+            }
+        }
+        U256(result)
+    }
+}
+
+impl Div for U256 {
+    type Output = U256;
+
+    // Integer division, dividing by zero returns U256::MAX
+    //
+    // The divisor must be below 2^255 so the shifted remainder can't overflow
+    fn div(self, divisor: U256) -> U256 {
+        if divisor == U256::ZERO {
+            return U256::MAX;
+        }
+
+        let mut quotient = U256::ZERO;
+        let mut remainder = U256::ZERO;
+        for i in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            if self.bit(i) {
+                remainder.0[3] |= 1;
+            }
+            if remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.0[3 - (i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        quotient
+    }
+}
+
+impl fmt::Display for U256 {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&hex::encode(self.to_be_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::U256;
+
+    #[test]
+    fn compact_round_trips() {
+        for bits in [0x1d00_ffff, 0x1f00_ffff, 0x2100_ffff, 0x0312_3456, 0x0412_3456, 0x0101_0000] {
+            assert_eq!(U256::from_compact(bits).to_compact(), bits);
+        }
+        assert_eq!(U256::from_compact(0x0312_3456), U256::from_u64(0x12_3456));
+        assert_eq!(U256::from_compact(0x0412_3456), U256::from_u64(0x1234_5600));
+
+        // Short sizes drop the low mantissa bytes and encode back in canonical form
+        assert_eq!(U256::from_compact(0x0200_1234), U256::from_u64(0x12));
+        assert_eq!(U256::from_compact(0x0200_1234).to_compact(), 0x0112_0000);
+    }
+
+    #[test]
+    fn compact_avoids_the_sign_bit() {
+        // 0x80 as the top mantissa byte would read back as negative
+        let target = U256::from_u64(0x80);
+        assert_eq!(target.to_compact(), 0x0200_8000);
+        assert_eq!(U256::from_compact(0x0200_8000), target);
+
+        let target = U256::from_u64(0xff_ffff);
+        assert_eq!(target.to_compact(), 0x0400_ffff);
+        assert_eq!(U256::from_compact(target.to_compact()), U256::from_u64(0xff_ff00));
+    }
+
+    #[test]
+    fn negative_and_empty_compact_decode_to_zero() {
+        assert_eq!(U256::from_compact(0x0380_0001), U256::ZERO);
+        assert_eq!(U256::from_compact(0x1d80_ffff), U256::ZERO);
+        assert_eq!(U256::from_compact(0x1d00_0000), U256::ZERO);
+        assert_eq!(U256::ZERO.to_compact(), 0);
+    }
+
+    #[test]
+    fn mul_div_keeps_the_intermediate_product() {
+        assert_eq!(U256::from_u64(100).mul_div(3, 4), U256::from_u64(75));
+        assert_eq!(U256::from_u64(7).mul_div(1, 2), U256::from_u64(3));
+
+        // The product overflows 256 bits before the division brings it back
+        let target = U256::MAX >> 1;
+        assert_eq!(target.mul_div(4, 4), target);
+        assert_eq!(target.mul_div(u64::MAX, u64::MAX), target);
+        assert_eq!(target.mul_div(1, 2), U256::MAX >> 2);
+        assert_eq!(U256::MAX.mul_div(2, 1), U256::MAX);
+    }
+
+    #[test]
+    fn divides() {
+        assert_eq!(U256::from_u64(100) / U256::from_u64(7), U256::from_u64(14));
+        assert_eq!(U256::from_u64(7) / U256::from_u64(100), U256::ZERO);
+        assert_eq!((U256::ONE << 200) / (U256::ONE << 100), U256::ONE << 100);
+        assert_eq!(U256::MAX / U256::ONE, U256::MAX);
+        assert_eq!(U256::ONE / U256::ZERO, U256::MAX);
+    }
+
+    #[test]
+    fn work_is_inverse_of_target() {
+        assert_eq!(U256::MAX.work(), U256::ONE);
+        assert_eq!(U256::ZERO.work(), U256::MAX);
+        assert_eq!((U256::MAX >> 1).work(), U256::from_u64(2));
+        assert_eq!((U256::MAX >> 8).work(), U256::from_u64(256));
+
+        // A target twice as hard needs twice the work
+        let target = U256::from_compact(0x1d00_ffff);
+        assert_eq!(target.work(), U256::from_u64(0x1_0001_0001));
+        assert_eq!((target >> 1).work(), U256::from_u64(0x2_0002_0002));
+    }
+}
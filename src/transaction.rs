@@ -3,51 +3,233 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use sha2::{Sha256, Digest};
 use std::fmt;
 
-#[derive(Debug)]
+// A reference to an output of an earlier transaction
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    pub hash: String,
+    pub index: u64
+}
+
+// Coins sent to an address by a transaction
+//
+// A data output instead carries a payload committed to by the transaction. It
+// has no address or amount, so it can never be spent and is left out of the
+// unspent outputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    pub address: String,
+    pub amount: i64,
+    pub data: Option<Vec<u8>>
+}
+
+// A block index or timestamp a transaction is locked until
+//
+// As a relative lock the value counts from the block that confirmed the spent
+// coins instead, in blocks or seconds. Seconds count from the median time past
+// of the blocks before it, like the median time past a lock is checked against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockTime {
+    Height(u64),
+    Time(i64)
+}
+
+#[derive(Debug, Clone)]
 pub struct Transaction {
+    // Owner of every spent output, who signs the transaction
     pub from: String,
-    pub to: String,
+    // Must be above the nonce of every earlier transaction from the same sender,
+    // so a signed transaction can't be replayed
+    pub nonce: u64,
+    pub inputs: Vec<OutPoint>,
+    pub outputs: Vec<Output>,
     pub timestamp: i64,
-    pub amount: i64
+    // The inputs must add up to the outputs plus this fee, which is claimed by
+    // the miner
+    pub fee: i64,
+    // The transaction can't be included in a block before this height or time
+    pub lock_time: Option<LockTime>,
+    // Every input must have been confirmed at least this many blocks or seconds
+    // before the block including the transaction
+    pub relative_lock: Option<LockTime>
+}
+
+impl<'a> OutPoint {
+    pub fn new(hash: &'a str, index: u64) -> OutPoint {
+        OutPoint {
+            hash: hash.to_string(),
+            index
+        }
+    }
+
+    // Reward transactions don't spend any coins. Instead they take a single
+    // placeholder input with a hash of "0" and the index of their block, which
+    // keeps rewards to the same address from sharing a hash.
+    pub fn reward(block_index: u64) -> OutPoint {
+        OutPoint::new("0", block_index)
+    }
+}
+
+impl<'a> Output {
+    pub fn new(address: &'a str, amount: i64) -> Output {
+        Output {
+            address: address.to_string(),
+            amount,
+            data: None
+        }
+    }
+
+    // Creates a zero value output carrying a data payload
+    pub fn new_data(data: Vec<u8>) -> Output {
+        Output {
+            address: String::new(),
+            amount: 0,
+            data: Some(data)
+        }
+    }
+
+    pub fn is_data(&self) -> bool {
+        self.data.is_some()
+    }
+}
+
+impl LockTime {
+    // Checks whether a lock has expired for a block at height whose previous
+    // blocks have a median time past of time
+    pub fn is_expired(&self, height: u64, time: i64) -> bool {
+        match *self {
+            LockTime::Height(lock) => height >= lock,
+            LockTime::Time(lock) => time >= lock
+        }
+    }
+
+    // Checks whether a relative lock has expired for coins confirmed at
+    // confirmed_height and confirmed_time
+    pub fn is_expired_since(&self, confirmed_height: u64, confirmed_time: i64, height: u64, time: i64) -> bool {
+        match *self {
+            LockTime::Height(lock) => confirmed_height.checked_add(lock)
+                .is_some_and(|lock| height >= lock),
+            LockTime::Time(lock) => confirmed_time.checked_add(lock)
+                .is_some_and(|lock| time >= lock)
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match *self {
+            LockTime::Height(height) => {
+                bytes.push(1);
+                bytes.write_u64::<LittleEndian>(height).expect("Unable to serialize lock height");
+            },
+            LockTime::Time(time) => {
+                bytes.push(2);
+                bytes.write_i64::<LittleEndian>(time).expect("Unable to serialize lock time");
+            }
+        }
+        bytes
+    }
 }
 
 impl<'a> Transaction {
     // Creates a new transaction
-    pub fn new(to: &'a str, from: &'a str, timestamp: i64, amount: i64) -> Transaction {
+    pub fn new(from: &'a str, nonce: u64, inputs: Vec<OutPoint>, outputs: Vec<Output>, timestamp: i64, fee: i64) -> Transaction {
         Transaction {
-            to: to.to_string(),
             from: from.to_string(),
-            timestamp: timestamp,
-            amount: amount
+            nonce,
+            inputs,
+            outputs,
+            timestamp,
+            fee,
+            lock_time: None,
+            relative_lock: None
         }
     }
 
     // Creates a new transaction with the current timestamp
-    pub fn create(to: &'a str, from: &'a str, amount: i64) -> Transaction {
+    pub fn create(from: &'a str, nonce: u64, inputs: Vec<OutPoint>, outputs: Vec<Output>, fee: i64) -> Transaction {
         let local_time = Local::now();
         let utc_time = DateTime::<Utc>::from_utc(local_time.naive_utc(), Utc);
 
-        Transaction {
-            to: to.to_string(),
-            from: from.to_string(),
-            timestamp: utc_time.timestamp(),
-            amount: amount
-        }
+        Transaction::new(from, nonce, inputs, outputs, utc_time.timestamp(), fee)
+    }
+
+    // Locks the transaction until a block height or time, see is_final
+    pub fn with_lock_time(mut self, lock_time: LockTime) -> Transaction {
+        self.lock_time = Some(lock_time);
+        self
+    }
+
+    // Locks the transaction until its inputs have been confirmed for a number of
+    // blocks or seconds
+    pub fn with_relative_lock(mut self, relative_lock: LockTime) -> Transaction {
+        self.relative_lock = Some(relative_lock);
+        self
+    }
+
+    // Attaches a data payload in a new data output, see Output::new_data
+    pub fn with_data(mut self, data: Vec<u8>) -> Transaction {
+        self.outputs.push(Output::new_data(data));
+        self
+    }
+
+    // Returns the payload of the transaction's data output, if it has one
+    pub fn data(&self) -> Option<&[u8]> {
+        self.outputs.iter().find_map(|output| output.data.as_deref())
+    }
+
+    // Checks whether the transaction's lock_time allows it in the block at height
+    // building on a chain with a median time past of time
+    pub fn is_final(&self, height: u64, time: i64) -> bool {
+        self.lock_time.is_none_or(|lock_time| lock_time.is_expired(height, time))
+    }
+
+    // The sum of all outputs, or None if it overflows
+    pub fn output_total(&self) -> Option<i64> {
+        self.outputs.iter()
+            .try_fold(0i64, |total, output| total.checked_add(output.amount))
+    }
+
+    // The outputs plus fee the inputs have to cover, or None if it overflows
+    pub fn cost(&self) -> Option<i64> {
+        self.output_total()?.checked_add(self.fee)
     }
 
     // Converts all transaction fields to a byte vector
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut amount = [0u8; std::mem::size_of::<i64>()];
-        amount.as_mut()
-            .write_i64::<LittleEndian>(self.amount)
-            .expect("Unable to write");
+        let mut bytes = Vec::new();
+        write_string(&mut bytes, &self.from);
+        bytes.write_u64::<LittleEndian>(self.nonce).expect("Unable to serialize nonce");
+
+        bytes.write_u32::<LittleEndian>(self.inputs.len() as u32).expect("Unable to serialize inputs");
+        for input in self.inputs.iter() {
+            write_string(&mut bytes, &input.hash);
+            bytes.write_u64::<LittleEndian>(input.index).expect("Unable to serialize input");
+        }
 
-        let timestamp = [0u8; std::mem::size_of::<i64>()];
-        amount.as_mut()
-            .write_i64::<LittleEndian>(self.timestamp)
-            .expect("Unable to write");
-        
-        [self.to.as_bytes(), self.from.as_bytes(), &amount, &timestamp].concat()
+        bytes.write_u32::<LittleEndian>(self.outputs.len() as u32).expect("Unable to serialize outputs");
+        for output in self.outputs.iter() {
+            write_string(&mut bytes, &output.address);
+            bytes.write_i64::<LittleEndian>(output.amount).expect("Unable to serialize output");
+            match &output.data {
+                Some(data) => {
+                    bytes.push(1);
+                    bytes.write_u32::<LittleEndian>(data.len() as u32).expect("Unable to serialize data");
+                    bytes.extend_from_slice(data);
+                },
+                None => bytes.push(0)
+            }
+        }
+
+        bytes.write_i64::<LittleEndian>(self.timestamp).expect("Unable to serialize timestamp");
+        bytes.write_i64::<LittleEndian>(self.fee).expect("Unable to serialize fee");
+
+        // A zero byte marks a missing lock
+        for lock in [self.lock_time, self.relative_lock].iter() {
+            match lock {
+                Some(lock) => bytes.extend_from_slice(&lock.as_bytes()),
+                None => bytes.push(0)
+            }
+        }
+        bytes
     }
 
     // Hashes all transaction fields
@@ -62,8 +244,32 @@ impl<'a> Transaction {
     }
 }
 
-impl<'a> fmt::Display for Transaction {
+impl fmt::Display for OutPoint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_fmt(format_args!("{}:{}", self.hash, self.index))
+    }
+}
+
+impl fmt::Display for Output {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        fmt.write_fmt(format_args!("Transaction(to: {}, from: {}, amount: {})", self.to, self.from, self.amount))
+        match &self.data {
+            Some(data) => fmt.write_fmt(format_args!("data {}", hex::encode(data))),
+            None => fmt.write_fmt(format_args!("{} to {}", self.amount, self.address))
+        }
+    }
+}
+
+// Writes a string with its length in front, so the bytes of neighbouring fields
+// can't be mistaken for part of it
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    bytes.write_u32::<LittleEndian>(value.len() as u32).expect("Unable to serialize string length");
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let inputs: Vec<String> = self.inputs.iter().map(OutPoint::to_string).collect();
+        let outputs: Vec<String> = self.outputs.iter().map(Output::to_string).collect();
+        fmt.write_fmt(format_args!("Transaction(from: {}, nonce: {}, inputs: [{}], outputs: [{}], fee: {})", self.from, self.nonce, inputs.join(", "), outputs.join(", "), self.fee))
     }
-}
\ No newline at end of file
+}
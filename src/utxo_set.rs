@@ -0,0 +1,339 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::block::Block;
+use crate::transaction::{OutPoint, Output};
+
+// An unspent transaction output along with where it was created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+    pub output: Output,
+    // Index of the block that created the output
+    pub height: u64,
+    // Median time past of the blocks before the one that created the output,
+    // relative time locks count from here
+    pub time: i64,
+    pub is_reward: bool
+}
+
+// The unspent outputs of the active chain, indexed by address for balance lookups
+//
+// Also tracks the last nonce used by each sender, see Transaction::nonce.
+#[derive(Debug, Clone, Default)]
+pub struct UtxoSet {
+    utxos: HashMap<OutPoint, Utxo>,
+    by_address: HashMap<String, HashSet<OutPoint>>,
+    nonces: HashMap<String, u64>
+}
+
+// Changes to a UtxoSet that haven't been applied yet
+//
+// Used to validate blocks and pending transactions without touching the set,
+// and to work out the set at the tip of a side chain. Once a block has been
+// checked the changes can be applied with UtxoSet::apply.
+pub struct UtxoView<'a> {
+    base: &'a UtxoSet,
+    added: HashMap<OutPoint, Utxo>,
+    spent: HashSet<OutPoint>,
+    nonces: HashMap<String, Option<u64>>
+}
+
+// Changes collected by a UtxoView, see UtxoView::into_changes
+pub struct UtxoChanges {
+    added: HashMap<OutPoint, Utxo>,
+    spent: HashSet<OutPoint>,
+    nonces: HashMap<String, Option<u64>>
+}
+
+// What connecting a block replaced, needed to disconnect it again
+#[derive(Debug, Clone, Default)]
+pub struct BlockUndo {
+    pub spent: Vec<(OutPoint, Utxo)>,
+    // Each sender's previous nonce, in the order the block's transactions set them
+    pub nonces: Vec<(String, Option<u64>)>
+}
+
+impl Utxo {
+    // Coins from reward transactions can only be spent by a block reward_maturity
+    // blocks after the one that created them. Genesis allocations are always
+    // spendable.
+    pub fn is_mature(&self, next_index: u64, reward_maturity: u64) -> bool {
+        !self.is_reward || self.height == 0 || self.height.saturating_add(reward_maturity) <= next_index
+    }
+}
+
+impl UtxoSet {
+    pub fn new() -> UtxoSet {
+        UtxoSet {
+            utxos: HashMap::new(),
+            by_address: HashMap::new(),
+            nonces: HashMap::new()
+        }
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&Utxo> {
+        self.utxos.get(outpoint)
+    }
+
+    pub fn len(&self) -> usize {
+        self.utxos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.utxos.is_empty()
+    }
+
+    // Returns the unspent outputs sent to an address
+    pub fn outputs_for(&self, address: &str) -> Vec<(&OutPoint, &Utxo)> {
+        match self.by_address.get(address) {
+            Some(outpoints) => outpoints.iter()
+                .map(|outpoint| (outpoint, &self.utxos[outpoint]))
+                .collect(),
+            None => Vec::new()
+        }
+    }
+
+    // Sums the unspent outputs sent to an address
+    pub fn balance(&self, address: &str) -> i64 {
+        self.outputs_for(address).iter()
+            .map(|(_, utxo)| utxo.output.amount)
+            .sum()
+    }
+
+    // Returns the last nonce used by a sender, or None if it hasn't sent anything
+    pub fn nonce(&self, address: &str) -> Option<u64> {
+        self.nonces.get(address).copied()
+    }
+
+    // Applies the changes collected by a view of this set
+    pub fn apply(&mut self, changes: UtxoChanges) {
+        for outpoint in changes.spent {
+            self.remove(&outpoint);
+        }
+        for (outpoint, utxo) in changes.added {
+            self.insert(outpoint, utxo);
+        }
+        for (address, nonce) in changes.nonces {
+            match nonce {
+                Some(nonce) => self.nonces.insert(address, nonce),
+                None => self.nonces.remove(&address)
+            };
+        }
+    }
+
+    fn insert(&mut self, outpoint: OutPoint, utxo: Utxo) {
+        self.remove(&outpoint);
+        self.by_address.entry(utxo.output.address.clone())
+            .or_default()
+            .insert(outpoint.clone());
+        self.utxos.insert(outpoint, utxo);
+    }
+
+    fn remove(&mut self, outpoint: &OutPoint) -> Option<Utxo> {
+        let utxo = self.utxos.remove(outpoint)?;
+        if let Some(outpoints) = self.by_address.get_mut(&utxo.output.address) {
+            outpoints.remove(outpoint);
+            if outpoints.is_empty() {
+                self.by_address.remove(&utxo.output.address);
+            }
+        }
+        Some(utxo)
+    }
+}
+
+impl<'a> UtxoView<'a> {
+    pub fn new(base: &'a UtxoSet) -> UtxoView<'a> {
+        UtxoView {
+            base,
+            added: HashMap::new(),
+            spent: HashSet::new(),
+            nonces: HashMap::new()
+        }
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&Utxo> {
+        if let Some(utxo) = self.added.get(outpoint) {
+            return Some(utxo);
+        }
+        if self.spent.contains(outpoint) {
+            return None;
+        }
+        self.base.get(outpoint)
+    }
+
+    pub fn insert(&mut self, outpoint: OutPoint, utxo: Utxo) {
+        self.spent.remove(&outpoint);
+        self.added.insert(outpoint, utxo);
+    }
+
+    pub fn remove(&mut self, outpoint: &OutPoint) -> Option<Utxo> {
+        let utxo = self.get(outpoint).cloned()?;
+        self.added.remove(outpoint);
+        if self.base.get(outpoint).is_some() {
+            self.spent.insert(outpoint.clone());
+        }
+        Some(utxo)
+    }
+
+    pub fn nonce(&self, address: &str) -> Option<u64> {
+        match self.nonces.get(address) {
+            Some(nonce) => *nonce,
+            None => self.base.nonce(address)
+        }
+    }
+
+    pub fn set_nonce(&mut self, address: &str, nonce: Option<u64>) {
+        self.nonces.insert(address.to_string(), nonce);
+    }
+
+    // Spends the inputs, adds the outputs and records the sender nonce of every
+    // transaction in a block, given the median time past of the blocks before it
+    //
+    // Returns what was replaced, which is needed to disconnect the block again.
+    pub fn connect_block(&mut self, block: &Block, median_time_past: i64) -> BlockUndo {
+        let mut undo = BlockUndo::default();
+        for signed_transaction in block.transactions.iter() {
+            let transaction = &signed_transaction.transaction;
+            if !signed_transaction.is_reward() {
+                for input in transaction.inputs.iter() {
+                    if let Some(utxo) = self.remove(input) {
+                        undo.spent.push((input.clone(), utxo));
+                    }
+                }
+
+                undo.nonces.push((transaction.from.clone(), self.nonce(&transaction.from)));
+                self.set_nonce(&transaction.from, Some(transaction.nonce));
+            }
+
+            for (index, output) in transaction.outputs.iter().enumerate().filter(|(_, output)| !output.is_data()) {
+                let utxo = Utxo {
+                    output: output.clone(),
+                    height: block.index,
+                    time: median_time_past,
+                    is_reward: signed_transaction.is_reward()
+                };
+                self.insert(signed_transaction.outpoint(index as u64), utxo);
+            }
+        }
+        undo
+    }
+
+    // Reverses connect_block given what it replaced
+    pub fn disconnect_block(&mut self, block: &Block, undo: &BlockUndo) {
+        let spent: HashMap<&OutPoint, &Utxo> = undo.spent.iter()
+            .map(|(outpoint, utxo)| (outpoint, utxo))
+            .collect();
+
+        // Undo transactions last to first so outputs spent within the block are
+        // removed again by the transaction that created them
+        for signed_transaction in block.transactions.iter().rev() {
+            for index in 0..signed_transaction.transaction.outputs.len() {
+                self.remove(&signed_transaction.outpoint(index as u64));
+            }
+
+            if !signed_transaction.is_reward() {
+                for input in signed_transaction.transaction.inputs.iter() {
+                    if let Some(utxo) = spent.get(input) {
+                        self.insert(input.clone(), (*utxo).clone());
+                    }
+                }
+            }
+        }
+
+        for (address, nonce) in undo.nonces.iter().rev() {
+            self.set_nonce(address, *nonce);
+        }
+    }
+
+    // Returns the changes made to the base set, see UtxoSet::apply
+    pub fn into_changes(self) -> UtxoChanges {
+        UtxoChanges {
+            added: self.added,
+            spent: self.spent,
+            nonces: self.nonces
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypair::Keypair;
+    use crate::signed_transaction::SignedTransaction;
+    use crate::transaction::Transaction;
+
+    fn transaction(keypair: &Keypair, nonce: u64, inputs: Vec<OutPoint>, outputs: Vec<Output>) -> SignedTransaction {
+        let transaction = Transaction::create(&keypair.export_public_key(), nonce, inputs, outputs, 0);
+        SignedTransaction::create(transaction, keypair)
+    }
+
+    fn outpoints(block: &Block) -> Vec<OutPoint> {
+        block.transactions.iter()
+            .flat_map(|transaction| (0..transaction.transaction.outputs.len() as u64).map(move |index| transaction.outpoint(index)))
+            .collect()
+    }
+
+    #[test]
+    fn disconnect_block_reverses_connect_block() {
+        let (alice, bob) = (Keypair::new(), Keypair::new());
+        let (alice_address, bob_address) = (alice.export_public_key(), bob.export_public_key());
+
+        let allocation = SignedTransaction::create_allocation(vec![Output::new(&alice_address, 100), Output::new(&bob_address, 50)], 0);
+        let genesis = Block::new(0, vec![allocation.clone()], "", 0, 0x2100_ffff, 0);
+        let mut set = UtxoSet::new();
+        let mut view = UtxoView::new(&set);
+        view.connect_block(&genesis, 0);
+        let changes = view.into_changes();
+        set.apply(changes);
+        set.nonces.insert(bob_address.clone(), 4);
+
+        // Alice pays Bob, who spends that output in the same block
+        let payment = transaction(&alice, 0, vec![allocation.outpoint(0)], vec![Output::new(&bob_address, 100)]);
+        let spend = transaction(&bob, 5, vec![payment.outpoint(0), allocation.outpoint(1)], vec![
+            Output::new(&alice_address, 120),
+            Output::new(&bob_address, 30),
+            Output::new_data(vec![1, 2, 3])
+        ]);
+        let reward = SignedTransaction::create_reward(&alice_address, 10, 1);
+        let block = Block::new(1, vec![reward, payment.clone(), spend.clone()], &genesis.hash, 1, 0x2100_ffff, 0);
+
+        let mut view = UtxoView::new(&set);
+        let undo = view.connect_block(&block, 1);
+        assert_eq!(undo.spent.len(), 3);
+        assert_eq!(view.get(&allocation.outpoint(0)), None);
+        assert_eq!(view.get(&payment.outpoint(0)), None);
+        assert_eq!(view.get(&spend.outpoint(0)).map(|utxo| utxo.output.amount), Some(120));
+        assert_eq!(view.get(&spend.outpoint(2)), None);
+        assert_eq!(view.nonce(&alice_address), Some(0));
+        assert_eq!(view.nonce(&bob_address), Some(5));
+
+        // Disconnecting within the same view leaves it as it started
+        view.disconnect_block(&block, &undo);
+        for outpoint in outpoints(&genesis).iter().chain(outpoints(&block).iter()) {
+            assert_eq!(view.get(outpoint), set.get(outpoint));
+        }
+        assert_eq!(view.nonce(&alice_address), None);
+        assert_eq!(view.nonce(&bob_address), Some(4));
+
+        // As does disconnecting from a set the block was applied to, as a reorg does
+        let mut connected = set.clone();
+        let mut view = UtxoView::new(&connected);
+        let undo = view.connect_block(&block, 1);
+        let changes = view.into_changes();
+        connected.apply(changes);
+        assert_eq!(connected.balance(&alice_address), 130);
+        assert_eq!(connected.balance(&bob_address), 30);
+
+        let mut view = UtxoView::new(&connected);
+        view.disconnect_block(&block, &undo);
+        let changes = view.into_changes();
+        connected.apply(changes);
+        for outpoint in outpoints(&genesis).iter().chain(outpoints(&block).iter()) {
+            assert_eq!(connected.get(outpoint), set.get(outpoint));
+        }
+        assert_eq!(connected.len(), set.len());
+        assert_eq!(connected.balance(&alice_address), 100);
+        assert_eq!(connected.balance(&bob_address), 50);
+        assert_eq!(connected.nonce(&alice_address), None);
+        assert_eq!(connected.nonce(&bob_address), Some(4));
+    }
+}
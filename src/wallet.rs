@@ -1,32 +1,212 @@
-use crate::keypair::Keypair;
-use crate::blockchain::Transaction;
+use std::cmp::Reverse;
+
+use crate::keypair::{Keypair, SignatureScheme};
+use crate::blockchain::{LockTime, Output, Transaction};
 use crate::blockchain::SignedTransaction;
+use crate::blockchain::Blockchain;
+use crate::error::WalletError;
+use crate::htlc::Htlc;
+use crate::script::Script;
 
 pub struct Wallet {
     pub keypair: Keypair,
+    // Scheme used for every signature the wallet makes, ECDSA by default
+    pub signature_scheme: SignatureScheme
+}
+
+// A wallet balance split by whether the coins can be spent yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Balance {
+    pub spendable: i64,
+    // Coins from reward transactions that haven't reached reward_maturity
+    pub immature: i64
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Wallet {
     // Create a new ECC keypair
     pub fn new() -> Wallet {
         Wallet {
-            keypair: Keypair::new()
+            keypair: Keypair::new(),
+            signature_scheme: SignatureScheme::Ecdsa
         }
     }
 
     // Create new wallet from an existing private key
     pub fn new_from_key(key: String) -> Wallet {
         Wallet {
-            keypair: Keypair::new_from_slice(key.as_bytes())
+            keypair: Keypair::new_from_slice(key.as_bytes()),
+            signature_scheme: SignatureScheme::Ecdsa
+        }
+    }
+
+    // Looks up the balance for this wallet's address in the active chain
+    pub fn balance(&self, blockchain: &Blockchain) -> Balance {
+        let address = self.keypair.export_public_key();
+        let immature = blockchain.calculate_immature_balance(&address);
+        Balance {
+            spendable: blockchain.calculate_balance(&address) - immature,
+            immature
+        }
+    }
+
+    // Signs a transaction with this wallet's key, used to collect signatures for
+    // a transaction from a multisig address, see SignedTransaction::create_multisig
+    pub fn sign(&self, transaction: &Transaction) -> String {
+        self.keypair.sign_with(&transaction.as_hash(), self.signature_scheme)
+    }
+
+    // Builds and signs a transaction paying amount to an address
+    pub fn create_transaction(&self, blockchain: &Blockchain, to: &str, amount: i64, fee: i64) -> Result<SignedTransaction, WalletError> {
+        self.create_payments(blockchain, vec![Output::new(to, amount)], fee)
+    }
+
+    // Builds and signs a single transaction paying every recipient
+    //
+    // The largest spendable outputs of this wallet are used until they cover
+    // the total of the payments and the fee, and whatever is left over is sent
+    // back to this wallet as change.
+    pub fn create_payments(&self, blockchain: &Blockchain, payments: Vec<Output>, fee: i64) -> Result<SignedTransaction, WalletError> {
+        let address = self.keypair.export_public_key();
+        let cost = payments.iter()
+            .try_fold(fee, |total, payment| total.checked_add(payment.amount))
+            .ok_or(WalletError::InsufficientFunds)?;
+
+        let mut available = blockchain.spendable_outputs(&address);
+        available.sort_by_key(|(_, utxo)| Reverse(utxo.output.amount));
+
+        let mut inputs = Vec::new();
+        let mut input_total: i64 = 0;
+        for (outpoint, utxo) in available {
+            if input_total >= cost {
+                break;
+            }
+            inputs.push(outpoint);
+            input_total += utxo.output.amount;
+        }
+        if input_total < cost {
+            return Err(WalletError::InsufficientFunds);
+        }
+
+        let mut outputs = payments;
+        if input_total > cost {
+            outputs.push(Output::new(&address, input_total - cost));
+        }
+
+        let nonce = blockchain.next_nonce(&address);
+        let transaction = Transaction::create(&address, nonce, inputs, outputs, fee);
+        Ok(SignedTransaction::create_with_scheme(transaction, &self.keypair, self.signature_scheme))
+    }
+
+    // Send some coins by adding a new transaction to the pending pool
+    pub fn send(&self, blockchain: &mut Blockchain, to: &str, amount: i64, fee: i64) -> Result<SignedTransaction, WalletError> {
+        self.send_payments(blockchain, vec![Output::new(to, amount)], fee)
+    }
+
+    // Pays many recipients with one pending transaction, see create_payments
+    pub fn send_payments(&self, blockchain: &mut Blockchain, payments: Vec<Output>, fee: i64) -> Result<SignedTransaction, WalletError> {
+        let signed_transaction = self.create_payments(blockchain, payments, fee)?;
+        blockchain.add_pending_transaction(signed_transaction.clone())?;
+        Ok(signed_transaction)
+    }
+
+    // Builds and signs a transaction committing a data payload to the chain, see
+    // Transaction::with_data
+    pub fn create_data(&self, blockchain: &Blockchain, data: Vec<u8>, fee: i64) -> Result<SignedTransaction, WalletError> {
+        self.create_payments(blockchain, vec![Output::new_data(data)], fee)
+    }
+
+    // Commits a data payload with a pending transaction, see create_data
+    pub fn send_data(&self, blockchain: &mut Blockchain, data: Vec<u8>, fee: i64) -> Result<SignedTransaction, WalletError> {
+        self.send_payments(blockchain, vec![Output::new_data(data)], fee)
+    }
+
+    // Replaces a pending transaction from this wallet with one paying a higher
+    // fee, see Blockchain::add_pending_transaction
+    //
+    // The transaction keeps its nonce, inputs and payments and the extra fee is
+    // taken from its change output.
+    pub fn bump_fee(&self, blockchain: &mut Blockchain, signed_transaction: &SignedTransaction, fee: i64) -> Result<SignedTransaction, WalletError> {
+        let address = self.keypair.export_public_key();
+        let mut transaction = signed_transaction.transaction.clone();
+        let extra = fee.checked_sub(transaction.fee).ok_or(WalletError::InsufficientFunds)?;
+        let change = transaction.outputs.iter().rposition(|output| output.address == address)
+            .ok_or(WalletError::InsufficientFunds)?;
+
+        let remaining = transaction.outputs[change].amount - extra;
+        if remaining < 0 {
+            return Err(WalletError::InsufficientFunds);
+        }
+        if remaining == 0 {
+            transaction.outputs.remove(change);
+        } else {
+            transaction.outputs[change].amount = remaining;
         }
+        transaction.fee = fee;
+
+        let replacement = SignedTransaction::create_with_scheme(transaction, &self.keypair, self.signature_scheme);
+        blockchain.add_pending_transaction(replacement.clone())?;
+        Ok(replacement)
     }
 
-    // Send some coins
-    pub fn send(&self, to: &str, amount: i64) {
-        let public_key = self.keypair.export_public_key();
-        let transaction = Transaction::create(to, &public_key, amount);
+    // Builds and signs a transaction locking amount in a contract that recipient
+    // can claim with the preimage of hash_lock, or this wallet can refund after
+    // timeout, see Htlc
+    pub fn create_htlc(&self, blockchain: &Blockchain, recipient: &str, amount: i64, fee: i64, hash_lock: [u8; 32], timeout: LockTime) -> Result<(Htlc, SignedTransaction), WalletError> {
+        let htlc = Htlc::new(&self.keypair.export_public_key(), recipient, hash_lock, timeout);
+        let signed_transaction = self.create_payments(blockchain, vec![Output::new(&htlc.address(), amount)], fee)?;
+        Ok((htlc, signed_transaction))
+    }
+
+    // Locks coins in a contract with a pending transaction, see create_htlc
+    pub fn send_htlc(&self, blockchain: &mut Blockchain, recipient: &str, amount: i64, fee: i64, hash_lock: [u8; 32], timeout: LockTime) -> Result<Htlc, WalletError> {
+        let (htlc, signed_transaction) = self.create_htlc(blockchain, recipient, amount, fee, hash_lock, timeout)?;
+        blockchain.add_pending_transaction(signed_transaction)?;
+        Ok(htlc)
+    }
+
+    // Claims the coins locked in a contract for this wallet with a pending
+    // transaction, revealing the preimage
+    pub fn claim_htlc(&self, blockchain: &mut Blockchain, htlc: &Htlc, preimage: &[u8], fee: i64) -> Result<SignedTransaction, WalletError> {
+        let transaction = self.create_htlc_spend(blockchain, htlc, fee)?;
+        let unlocking = htlc.claim_script(&self.sign(&transaction), preimage);
+        self.send_htlc_spend(blockchain, htlc, transaction, unlocking)
+    }
+
+    // Refunds the coins this wallet locked in a contract with a pending
+    // transaction, which is only valid once the timeout has passed
+    pub fn refund_htlc(&self, blockchain: &mut Blockchain, htlc: &Htlc, fee: i64) -> Result<SignedTransaction, WalletError> {
+        let transaction = self.create_htlc_spend(blockchain, htlc, fee)?;
+        let unlocking = htlc.refund_script(&self.sign(&transaction));
+        self.send_htlc_spend(blockchain, htlc, transaction, unlocking)
+    }
+
+    // Builds a transaction sending every spendable output of a contract to this
+    // wallet, less the fee
+    fn create_htlc_spend(&self, blockchain: &Blockchain, htlc: &Htlc, fee: i64) -> Result<Transaction, WalletError> {
+        let address = htlc.address();
+        let available = blockchain.spendable_outputs(&address);
+        let input_total = available.iter()
+            .try_fold(0i64, |total, (_, utxo)| total.checked_add(utxo.output.amount))
+            .ok_or(WalletError::InsufficientFunds)?;
+        if input_total <= fee {
+            return Err(WalletError::InsufficientFunds);
+        }
 
-        // Sign the new transaction
-        let _ = SignedTransaction::create(transaction, &self.keypair);
+        let inputs = available.into_iter().map(|(outpoint, _)| outpoint).collect();
+        let outputs = vec![Output::new(&self.keypair.export_public_key(), input_total - fee)];
+        let nonce = blockchain.next_nonce(&address);
+        Ok(Transaction::create(&address, nonce, inputs, outputs, fee))
     }
-}
\ No newline at end of file
+
+    fn send_htlc_spend(&self, blockchain: &mut Blockchain, htlc: &Htlc, transaction: Transaction, unlocking: Script) -> Result<SignedTransaction, WalletError> {
+        let signed_transaction = SignedTransaction::create_script(transaction, htlc.locking_script(), unlocking);
+        blockchain.add_pending_transaction(signed_transaction.clone())?;
+        Ok(signed_transaction)
+    }
+}